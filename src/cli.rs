@@ -0,0 +1,367 @@
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use clap::{Args, ValueEnum};
+
+use pnawir::{
+    modular_net::ModularPetrinet,
+    parser::{
+        parse_input::{parse, RawParserInput},
+        transform_input,
+    },
+    sync_reachability_graph::{
+        build_graph::build_sync_reachability_graph_with,
+        graph::Graph,
+        options::{ExploreObserver, ExploreOptions, ExploreStats, Reduction, Strategy},
+    },
+};
+
+pub mod batch;
+pub mod check;
+pub mod commands;
+pub mod format;
+pub mod logger;
+pub mod output;
+
+/// Exit codes of all subcommands
+pub mod exit {
+    pub const OK: i32 = 0;
+    /// the checked property does not hold
+    pub const VIOLATED: i32 = 1;
+    /// invalid arguments, also used by clap
+    pub const USAGE: i32 = 2;
+    /// the net or a formula is invalid
+    pub const INVALID: i32 = 3;
+    /// a limit stopped the exploration, the result is unknown
+    pub const INCOMPLETE: i32 = 4;
+    /// the net can not be read
+    pub const INPUT: i32 = 5;
+    /// the output can not be written
+    pub const OUTPUT: i32 = 6;
+}
+
+/// Error of a subcommand, printed to stderr before exiting with the code
+#[derive(Debug)]
+pub struct CliError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        CliError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(e: io::Error) -> Self {
+        CliError::new(exit::OUTPUT, e.to_string())
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Read the file, - reads from stdin
+pub fn read(filename: &str) -> Result<String, CliError> {
+    if filename == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input).map(|_| input)
+    } else {
+        fs::read_to_string(filename)
+    }
+    .map_err(|e| CliError::new(exit::INPUT, format!("{}: {}", filename, e)))
+}
+
+/// Parse the whole input, the error names the line where the parser stopped
+pub fn parse_raw(filename: &str, input: &str) -> Result<RawParserInput, CliError> {
+    // the rest of the input is where the parser stopped
+    let line = |rest: &str| {
+        let offset = input.len() - rest.trim_start().len();
+        input[..offset].matches('\n').count() + 1
+    };
+    match parse(input) {
+        Ok((rest, raw)) if rest.trim().is_empty() => Ok(raw),
+        Err(nom::Err::Error(nom::error::Error { input: rest, .. })) if rest.trim().is_empty() => {
+            Err(CliError::new(
+                exit::INVALID,
+                format!("{}: unexpected end of input", filename),
+            ))
+        }
+        Ok((rest, _)) | Err(nom::Err::Error(nom::error::Error { input: rest, .. })) => {
+            Err(CliError::new(
+                exit::INVALID,
+                format!(
+                    "{}:{}: unexpected input: {}",
+                    filename,
+                    line(rest),
+                    rest.trim().lines().next().unwrap_or_default()
+                ),
+            ))
+        }
+        Err(_) => Err(CliError::new(
+            exit::INVALID,
+            format!("{}: unexpected end of input", filename),
+        )),
+    }
+}
+
+/// Parse the input of the file after the files it includes, each file only once
+/// the includes are relative to the including file, or to the working directory for stdin
+fn parse_includes(
+    filename: &str,
+    input: &str,
+    seen: &mut HashSet<PathBuf>,
+    inputs: &mut Vec<RawParserInput>,
+) -> Result<(), CliError> {
+    let canonical = |filename: &str| {
+        fs::canonicalize(filename)
+            .map_err(|e| CliError::new(exit::INPUT, format!("{}: {}", filename, e)))
+    };
+    if filename != "-" {
+        seen.insert(canonical(filename)?);
+    }
+    let raw_parser_input = parse_raw(filename, input)?;
+    let dir = Path::new(filename).parent().unwrap_or(Path::new(""));
+    for include in &raw_parser_input.includes {
+        let path = dir.join(&include.path);
+        let path = path.to_string_lossy();
+        if !seen.contains(&canonical(&path)?) {
+            parse_includes(&path, &read(&path)?, seen, inputs)?;
+        }
+    }
+    inputs.push(raw_parser_input);
+    Ok(())
+}
+
+/// Read, parse and validate the net with its includes, - reads from stdin
+pub fn load(filename: &str) -> Result<ModularPetrinet, CliError> {
+    load_input(filename, &read(filename)?)
+}
+
+/// Parse and validate the input of the file with its includes
+/// stdin included again by a cycle declares its modules twice
+fn load_input(filename: &str, input: &str) -> Result<ModularPetrinet, CliError> {
+    let mut inputs = vec![];
+    parse_includes(filename, input, &mut HashSet::new(), &mut inputs)?;
+    let raw_parser_input = transform_input::merge(inputs);
+    transform_input::validate(&raw_parser_input)
+        .map_err(|e| CliError::new(exit::INVALID, format!("{}: {}", filename, e)))?;
+    Ok(transform_input::transform(raw_parser_input))
+}
+
+/// Options of the sync graph exploration, shared by the subcommands
+#[derive(Args, Debug, Clone)]
+pub struct ExploreArgs {
+    /// stop after this many sync nodes
+    #[arg(long)]
+    max_sync_nodes: Option<usize>,
+
+    /// cut off segments at this many markings
+    #[arg(long)]
+    max_segment_markings: Option<usize>,
+
+    /// stop the exploration after this many seconds
+    #[arg(long)]
+    timeout: Option<u64>,
+
+    /// traversal order of the exploration, bfs explores the whole frontier in parallel,
+    /// dfs and random as many sync nodes as there are threads
+    #[arg(long, value_enum, default_value_t = Order::Bfs)]
+    strategy: Order,
+
+    /// seed of the random order
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// stubborn set reduction of the segments, keeps deadlocks but not every path,
+    /// not for query, compare, liveness and boundedness
+    #[arg(long, value_enum, default_value_t = Por::None)]
+    reduction: Por,
+
+    /// merge sync nodes differing only by a permutation of identical modules,
+    /// not for compare and liveness, query formulas may not tell the modules apart
+    #[arg(long)]
+    symmetry: bool,
+
+    /// worker threads of the exploration, all cores by default,
+    /// only a single thread numbers the graph the same on every run
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Order {
+    Bfs,
+    Dfs,
+    Random,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Por {
+    None,
+    Deadlocks,
+    Interface,
+}
+
+impl ExploreArgs {
+    pub fn options(&self) -> ExploreOptions {
+        ExploreOptions {
+            max_sync_nodes: self.max_sync_nodes,
+            max_segment_markings: self.max_segment_markings,
+            deadline: self
+                .timeout
+                .map(|secs| Instant::now() + Duration::from_secs(secs)),
+            cancel: None,
+            observer: None,
+            strategy: match self.strategy {
+                Order::Bfs => Strategy::Bfs,
+                Order::Dfs => Strategy::Dfs,
+                Order::Random => Strategy::Random(self.seed),
+            },
+            reduction: match self.reduction {
+                Por::None => Reduction::None,
+                Por::Deadlocks => Reduction::Deadlocks,
+                Por::Interface => Reduction::Interface,
+            },
+            symmetry: self.symmetry,
+            threads: self.threads.map(usize::from),
+        }
+    }
+
+    /// the reductions in use, none or e.g. deadlocks+symmetry
+    pub fn reductions(&self) -> String {
+        let mut reductions = vec![];
+        if let Some(value) = self.reduction.to_possible_value() {
+            if !matches!(self.reduction, Por::None) {
+                reductions.push(value.get_name().to_string());
+            }
+        }
+        if self.symmetry {
+            reductions.push("symmetry".to_string());
+        }
+        if reductions.is_empty() {
+            "none".to_string()
+        } else {
+            reductions.join("+")
+        }
+    }
+
+    /// Usage error for the reductions which change the result of the subcommand or do not apply,
+    /// both keep the deadlocks but drop other states
+    pub fn unsupported(&self, what: &str, reduction: bool, symmetry: bool) -> Result<(), CliError> {
+        let flag = if reduction && !matches!(self.reduction, Por::None) {
+            "--reduction"
+        } else if symmetry && self.symmetry {
+            "--symmetry"
+        } else {
+            return Ok(());
+        };
+        Err(CliError::new(
+            exit::USAGE,
+            format!("{} can not be used with {}", flag, what),
+        ))
+    }
+}
+
+/// progress line on stderr if it is a terminal, keeps the last stats for the summary
+struct Progress {
+    show: bool,
+    last: Mutex<Option<ExploreStats>>,
+}
+
+impl ExploreObserver for Progress {
+    fn progress(&self, stats: &ExploreStats) {
+        if self.show {
+            eprint!(
+                "\rsync nodes: {} | segments: {:?} | markings: {} | frontier: {} | {:.1?}   ",
+                stats.sync_nodes, stats.segments, stats.markings, stats.frontier, stats.elapsed
+            );
+        }
+        *self.last.lock().unwrap() = Some(stats.clone());
+    }
+}
+
+/// Build the sync graph with a progress line, returns the final stats as well
+pub fn explore(net: &ModularPetrinet, args: &ExploreArgs) -> (Graph, ExploreStats) {
+    let progress = Arc::new(Progress {
+        show: io::stderr().is_terminal() && log::max_level() >= log::LevelFilter::Warn,
+        last: Mutex::new(None),
+    });
+    let options = ExploreOptions {
+        observer: Some(progress.clone()),
+        ..args.options()
+    };
+    let graph = build_sync_reachability_graph_with(net, &options);
+    if progress.show {
+        eprintln!();
+    }
+    let stats = progress.last.lock().unwrap().take();
+    let stats = stats.unwrap_or_else(|| ExploreStats::new(&graph, 0, Duration::ZERO));
+    (graph, stats)
+}
+
+/// the graph is complete, otherwise an error with the limit
+pub fn complete(graph: &Graph) -> Result<(), CliError> {
+    match graph.incomplete {
+        None => Ok(()),
+        Some(limit) => Err(CliError::new(
+            exit::INCOMPLETE,
+            format!("exploration incomplete, limit reached: {:?}", limit),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, env, fs};
+
+    use super::{exit, load, load_input, parse_includes};
+
+    #[test]
+    fn test_parse_includes() {
+        let dir = env::temp_dir().join(format!("pnawir-includes-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.pnawir"), dir.join("b.pnawir"));
+        let a_input = "include \"b.pnawir\"\n\nA {\n    p\n}\n";
+        fs::write(&a, a_input).unwrap();
+        fs::write(&b, "include \"a.pnawir\"\n\nB {\n    q\n}\n").unwrap();
+
+        // the cycle loads every file once, the included file first
+        let a = a.to_string_lossy();
+        let mut inputs = vec![];
+        parse_includes(&a, a_input, &mut HashSet::new(), &mut inputs).unwrap();
+        let names = inputs
+            .iter()
+            .map(|i| i.modules[0].name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["B", "A"]);
+        assert_eq!(load(&a).unwrap().modules.len(), 2);
+
+        // stdin has no path, the cycle is cut at b and a is loaded once besides stdin
+        let stdin = format!("include \"{}\"\n\nA {{\n    p\n}}\n", b.to_string_lossy());
+        let net = load_input("-", &stdin).unwrap();
+        let names = net
+            .modules
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["A", "B", "A"]);
+
+        let e = load_input("-", "include \"missing.pnawir\"\n\nA {\n    p\n}\n").unwrap_err();
+        assert_eq!(e.code, exit::INPUT);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
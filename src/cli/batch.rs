@@ -0,0 +1,287 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use pnawir::sync_reachability_graph::state_space::expand;
+
+use super::{
+    exit, explore, load,
+    output::{Format, Json},
+    CliError, ExploreArgs,
+};
+
+/// One line of the summary, the counts are missing if the net could not be analyzed
+#[derive(Debug, Default)]
+struct Row {
+    file: String,
+    modules: Option<usize>,
+    places: Option<usize>,
+    transitions: Option<usize>,
+    /// the counts below are of the reduced graph unless this is none
+    reduction: String,
+    sync_nodes: Option<usize>,
+    segments: Option<usize>,
+    markings: Option<usize>,
+    /// only for a complete graph
+    states: Option<usize>,
+    seconds: Option<f64>,
+    error: Option<String>,
+}
+
+const HEADER: [&str; 11] = [
+    "file",
+    "modules",
+    "places",
+    "transitions",
+    "reduction",
+    "sync_nodes",
+    "segments",
+    "markings",
+    "states",
+    "seconds",
+    "error",
+];
+
+impl Row {
+    fn cells(&self) -> [String; 11] {
+        let n = |x: Option<usize>| x.map_or(String::new(), |x| x.to_string());
+        [
+            self.file.clone(),
+            n(self.modules),
+            n(self.places),
+            n(self.transitions),
+            self.reduction.clone(),
+            n(self.sync_nodes),
+            n(self.segments),
+            n(self.markings),
+            n(self.states),
+            self.seconds.map_or(String::new(), |s| format!("{:.6}", s)),
+            self.error.clone().unwrap_or_default(),
+        ]
+    }
+
+    fn json(&self) -> Json {
+        Json::object([
+            ("file", self.file.as_str().into()),
+            ("modules", self.modules.into()),
+            ("places", self.places.into()),
+            ("transitions", self.transitions.into()),
+            ("reduction", self.reduction.as_str().into()),
+            ("sync_nodes", self.sync_nodes.into()),
+            ("segments", self.segments.into()),
+            ("markings", self.markings.into()),
+            ("states", self.states.into()),
+            ("seconds", self.seconds.into()),
+            ("error", self.error.clone().into()),
+        ])
+    }
+}
+
+/// files as given, directories replaced by their .pnawir files in name order
+fn collect(paths: &[PathBuf]) -> Result<Vec<PathBuf>, CliError> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let read =
+            |e: std::io::Error| CliError::new(exit::INPUT, format!("{}: {}", path.display(), e));
+        let mut entries = fs::read_dir(path)
+            .map_err(read)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(read)?;
+        entries.retain(|p| p.is_file() && p.extension().is_some_and(|e| e == "pnawir"));
+        entries.sort();
+        files.extend(entries);
+    }
+    Ok(files)
+}
+
+/// Build the sync graph of one net and expand it, errors end up in the row
+fn analyze(file: &Path, explore_args: &ExploreArgs) -> (Row, i32) {
+    let mut row = Row {
+        file: file.display().to_string(),
+        reduction: explore_args.reductions(),
+        ..Default::default()
+    };
+    let net = match load(&row.file) {
+        Ok(net) => net,
+        Err(e) => {
+            row.error = Some(e.message);
+            return (row, e.code);
+        }
+    };
+    let start = net.intern_transition_start as usize;
+    row.modules = Some(net.modules.len());
+    row.places = Some(net.modules.iter().map(|m| m.places.len()).sum());
+    let intern = net.modules.iter().map(|m| m.transitions.len() - start);
+    row.transitions = Some(start + intern.sum::<usize>());
+
+    let time = Instant::now();
+    let (graph, stats) = explore(&net, explore_args);
+    row.sync_nodes = Some(stats.sync_nodes);
+    row.segments = Some(stats.segments.iter().sum());
+    row.markings = Some(stats.markings);
+    let code = match graph.incomplete {
+        None => {
+            row.states = Some(expand(&net, &graph).states.len());
+            exit::OK
+        }
+        Some(limit) => {
+            row.error = Some(format!(
+                "exploration incomplete, limit reached: {:?}",
+                limit
+            ));
+            exit::INCOMPLETE
+        }
+    };
+    row.seconds = Some(time.elapsed().as_secs_f64());
+    (row, code)
+}
+
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Analyze every net and write one summary row each
+/// exit with the code of the first net which failed, the others are analyzed anyway
+pub fn batch(
+    paths: &[PathBuf],
+    explore_args: &ExploreArgs,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(&[Format::Text, Format::Json, Format::Csv], "batch")?;
+    let mut code = exit::OK;
+    let mut rows = vec![];
+    for file in collect(paths)? {
+        let (row, row_code) = analyze(&file, explore_args);
+        if code == exit::OK {
+            code = row_code;
+        }
+        rows.push(row);
+    }
+
+    match format {
+        Format::Json => {
+            writeln!(out, "{}", Json::array(rows.iter().map(Row::json)))?;
+        }
+        Format::Csv => {
+            writeln!(out, "{}", HEADER.join(","))?;
+            for row in &rows {
+                let cells = row.cells().map(|c| csv_cell(&c));
+                writeln!(out, "{}", cells.join(","))?;
+            }
+        }
+        _ => {
+            let cells = rows.iter().map(Row::cells).collect::<Vec<_>>();
+            let widths = (0..HEADER.len())
+                .map(|i| {
+                    let width = cells.iter().map(|c| c[i].len()).max().unwrap_or(0);
+                    width.max(HEADER[i].len())
+                })
+                .collect::<Vec<_>>();
+            let line = |cells: &[&str]| {
+                let padded = cells
+                    .iter()
+                    .zip(&widths)
+                    .map(|(c, &w)| format!("{:<w$}", c, w = w))
+                    .collect::<Vec<_>>();
+                padded.join("  ").trim_end().to_string()
+            };
+            writeln!(out, "{}", line(&HEADER))?;
+            for row in &cells {
+                let row = row.iter().map(String::as_str).collect::<Vec<_>>();
+                writeln!(out, "{}", line(&row))?;
+            }
+        }
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use super::{batch, exit, ExploreArgs, Format, HEADER};
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        explore: ExploreArgs,
+    }
+
+    fn run(paths: &[&str], flags: &[&str], format: Format) -> (i32, String) {
+        let args = Args::parse_from(["batch"].iter().chain(flags));
+        let paths = paths
+            .iter()
+            .map(|p| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(p))
+            .collect::<Vec<_>>();
+        let mut out = vec![];
+        let code = batch(&paths, &args.explore, format, &mut out).unwrap();
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_batch_csv() {
+        let (code, out) = run(&["examples/ba2022"], &[], Format::Csv);
+        assert_eq!(code, exit::OK);
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], HEADER.join(","));
+        // one row per file of the directory, in name order
+        let files = lines[1..]
+            .iter()
+            .map(|l| l.split(',').next().unwrap().rsplit('/').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            files,
+            [
+                "P001-1.pnawir",
+                "P001.pnawir",
+                "P002-1.pnawir",
+                "P002.pnawir",
+                "P003-1.pnawir",
+                "P003.pnawir",
+                "P004.pnawir",
+                "P005.pnawir",
+                "P006.pnawir",
+                "P006a.pnawir",
+            ]
+        );
+        for line in &lines[1..] {
+            let cells = line.split(',').collect::<Vec<_>>();
+            assert_eq!(cells.len(), HEADER.len());
+            assert_eq!(cells[4], "none");
+            // complete graph without error
+            assert!(cells[8].parse::<usize>().is_ok());
+            assert_eq!(cells[10], "");
+        }
+    }
+
+    #[test]
+    fn test_batch_json() {
+        let (code, out) = run(
+            &["examples/ba2022/P005.pnawir", "examples/missing.pnawir"],
+            &["--reduction", "deadlocks", "--symmetry"],
+            Format::Json,
+        );
+        // the first failing net gives the exit code
+        assert_eq!(code, exit::INPUT);
+        assert!(out.starts_with('[') && out.trim_end().ends_with(']'));
+        assert_eq!(out.matches("\"file\":").count(), 2);
+        assert!(out.contains("\"reduction\":\"deadlocks+symmetry\""));
+        assert!(out.contains("\"states\":3"));
+        assert!(out.contains("\"sync_nodes\":null"));
+    }
+}
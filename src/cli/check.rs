@@ -0,0 +1,259 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use pnawir::{
+    model_checking::ctl,
+    modular_net::ModularPetrinet,
+    structural::{
+        flat_net::FlatNet,
+        invariants::{p_invariants, place_bounds},
+    },
+    sync_reachability_graph::{
+        flat_graph::{coverability, Bound},
+        graph::Graph,
+        state_space::StateSpace,
+    },
+};
+
+use super::{
+    commands::trace_json, complete, exit, explore, output::Format, output::Json, CliError,
+    ExploreArgs,
+};
+
+/// Behavioural properties of the composed net
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Property {
+    /// no reachable marking without an enabled transition
+    Deadlock,
+    /// every transition can be enabled again from every reachable marking
+    Liveness,
+    /// every place has a maximal token count
+    Boundedness,
+}
+
+/// Outcome of one property, None if the exploration was incomplete
+struct Outcome {
+    property: Property,
+    holds: Option<bool>,
+    details: Vec<(&'static str, Json)>,
+    text: String,
+}
+
+/// The sync graph, explored at most once and only if needed
+/// its state space is expanded on demand by the checks
+struct Explored<'a> {
+    net: &'a ModularPetrinet,
+    explore_args: &'a ExploreArgs,
+    /// None inside if the exploration was incomplete
+    result: Option<Option<(Graph, StateSpace)>>,
+}
+
+impl Explored<'_> {
+    fn get(&mut self) -> Option<(&Graph, &mut StateSpace)> {
+        self.result
+            .get_or_insert_with(|| {
+                let (graph, _) = explore(self.net, self.explore_args);
+                match complete(&graph) {
+                    Ok(()) => {
+                        let space = StateSpace::initial(&graph);
+                        Some((graph, space))
+                    }
+                    Err(e) => {
+                        log::warn!("{}", e);
+                        None
+                    }
+                }
+            })
+            .as_mut()
+            .map(|(graph, space)| (&*graph, space))
+    }
+}
+
+/// Check the properties, exit VIOLATED if one does not hold and INCOMPLETE if one is unknown
+pub fn check(
+    net: &ModularPetrinet,
+    properties: &[Property],
+    explore_args: &ExploreArgs,
+    with_trace: bool,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(&[Format::Text, Format::Json], "check")?;
+    let properties = if properties.is_empty() {
+        Property::value_variants()
+    } else {
+        properties
+    };
+    if properties.contains(&Property::Liveness) {
+        explore_args.unsupported("liveness", true, true)?;
+    }
+    if properties.contains(&Property::Boundedness) {
+        explore_args.unsupported("boundedness", true, false)?;
+    }
+
+    let mut explored = Explored {
+        net,
+        explore_args,
+        result: None,
+    };
+    let mut outcomes = vec![];
+    for &property in properties {
+        let outcome = match property {
+            Property::Boundedness => boundedness(net, explore_args),
+            Property::Deadlock => match explored.get() {
+                Some((graph, space)) => deadlock(net, graph, space, with_trace)?,
+                None => unknown(property),
+            },
+            Property::Liveness => match explored.get() {
+                Some((graph, space)) => liveness(net, graph, space)?,
+                None => unknown(property),
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    if format == Format::Json {
+        let fields = outcomes.iter().map(|o| {
+            let mut fields = vec![("holds", o.holds.into())];
+            fields.extend(o.details.iter().cloned());
+            (name(o.property), Json::object(fields))
+        });
+        writeln!(out, "{}", Json::object(fields))?;
+    } else {
+        for o in &outcomes {
+            let holds = o.holds.map_or("unknown".to_string(), |h| h.to_string());
+            writeln!(out, "{}: {}{}", name(o.property), holds, o.text)?;
+        }
+    }
+
+    let code = if outcomes.iter().any(|o| o.holds == Some(false)) {
+        exit::VIOLATED
+    } else if outcomes.iter().any(|o| o.holds.is_none()) {
+        exit::INCOMPLETE
+    } else {
+        exit::OK
+    };
+    Ok(code)
+}
+
+fn name(property: Property) -> &'static str {
+    match property {
+        Property::Deadlock => "deadlock_free",
+        Property::Liveness => "live",
+        Property::Boundedness => "bounded",
+    }
+}
+
+fn unknown(property: Property) -> Outcome {
+    Outcome {
+        property,
+        holds: None,
+        details: vec![],
+        text: String::new(),
+    }
+}
+
+fn deadlock(
+    net: &ModularPetrinet,
+    graph: &Graph,
+    space: &mut StateSpace,
+    with_trace: bool,
+) -> Result<Outcome, CliError> {
+    let formula = ctl::parse("AG !deadlock").expect("valid formula");
+    let result =
+        ctl::check(net, graph, space, &formula).map_err(|e| CliError::new(exit::INVALID, e))?;
+    let trace = result.trace.filter(|_| with_trace);
+    let mut text = String::new();
+    if let Some(trace) = &trace {
+        let mut buffer = vec![];
+        trace.write(net, graph, space, &mut buffer)?;
+        text = format!("\n{}", String::from_utf8_lossy(&buffer).trim_end());
+    }
+    Ok(Outcome {
+        property: Property::Deadlock,
+        holds: Some(result.holds),
+        details: vec![(
+            "trace",
+            trace.map(|t| trace_json(net, graph, space, &t)).into(),
+        )],
+        text,
+    })
+}
+
+/// L4-liveness: AG EF enabled(t) for every transition
+fn liveness(
+    net: &ModularPetrinet,
+    graph: &Graph,
+    space: &mut StateSpace,
+) -> Result<Outcome, CliError> {
+    let flat = FlatNet::new(net);
+    let mut not_live = vec![];
+    for t in 0..flat.transitions.len() {
+        let name = flat.transition_name(net, t);
+        let formula = ctl::parse(&format!("AG EF enabled({})", name))
+            .map_err(|e| CliError::new(exit::INVALID, e))?;
+        let result =
+            ctl::check(net, graph, space, &formula).map_err(|e| CliError::new(exit::INVALID, e))?;
+        if !result.holds {
+            not_live.push(name);
+        }
+    }
+    let text = if not_live.is_empty() {
+        String::new()
+    } else {
+        format!(" (not live: {})", not_live.join(", "))
+    };
+    Ok(Outcome {
+        property: Property::Liveness,
+        holds: Some(not_live.is_empty()),
+        details: vec![("not_live", Json::array(not_live))],
+        text,
+    })
+}
+
+/// structurally from the P-invariants, otherwise by a coverability search on the flat net
+/// which ends on unbounded nets as well, unknown if it reached the timeout
+fn boundedness(net: &ModularPetrinet, explore_args: &ExploreArgs) -> Outcome {
+    let flat = FlatNet::new(net);
+    let structural = match p_invariants(&flat) {
+        Ok(invariants) => place_bounds(&flat, &invariants)
+            .iter()
+            .try_fold(0, |max, b| b.map(|b| max.max(b))),
+        Err(e) => {
+            log::warn!("{}, falling back to the coverability search", e);
+            None
+        }
+    };
+    let (bound, method) = match structural {
+        Some(bound) => (bound, "structural"),
+        None => match coverability(&flat, &explore_args.options()) {
+            Bound::Bounded(bound) => (bound as u64, "coverability"),
+            Bound::Unbounded(places) => {
+                let places = places
+                    .iter()
+                    .map(|&p| flat.place_name(net, p))
+                    .collect::<Vec<_>>();
+                return Outcome {
+                    property: Property::Boundedness,
+                    holds: Some(false),
+                    text: format!(" (unbounded: {})", places.join(", ")),
+                    details: vec![
+                        ("unbounded", Json::array(places)),
+                        ("method", "coverability".into()),
+                    ],
+                };
+            }
+            Bound::Unknown(limit) => {
+                log::warn!("coverability search incomplete, limit reached: {:?}", limit);
+                return unknown(Property::Boundedness);
+            }
+        },
+    };
+    Outcome {
+        property: Property::Boundedness,
+        holds: Some(true),
+        details: vec![("bound", bound.into()), ("method", method.into())],
+        text: format!(" ({}-bounded, {})", bound, method),
+    }
+}
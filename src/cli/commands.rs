@@ -0,0 +1,548 @@
+use std::{io::Write, time::Instant};
+
+use clap::{ArgGroup, Args};
+
+use pnawir::{
+    model_checking::{
+        ctl, ltl,
+        trace::{CheckResult, Trace},
+    },
+    modular_net::{ModularPetrinet, ModuleId},
+    structural::{
+        classification::{classify, Classification},
+        flat_net::FlatNet,
+        state_equation::{check_target, parse_target, Verdict},
+    },
+    sync_reachability_graph::{
+        flat_graph::{build_flat_graph, distinct_markings},
+        graph::{Graph, Marking, SyncMarking},
+        options::Limit,
+        state_space::{expand, StateSpace},
+        symbolic::build_symbolic_graph_with,
+        symmetry::symmetric_modules,
+    },
+};
+
+use super::{
+    complete, exit, explore,
+    output::{Format, Json},
+    CliError, ExploreArgs,
+};
+
+const TEXT_OR_JSON: &[Format] = &[Format::Text, Format::Json];
+
+pub fn marking_json(net: &ModularPetrinet, m_id: ModuleId, marking: &Marking) -> Json {
+    let module = &net.modules[m_id as usize];
+    Json::Object(
+        marking
+            .place_counts
+            .iter()
+            .map(|pc| {
+                let name = module.places[pc.place_id as usize].name.clone();
+                (name, pc.count.into())
+            })
+            .collect(),
+    )
+}
+
+/// markings of all modules in one object
+fn state_json(net: &ModularPetrinet, graph: &Graph, space: &StateSpace, s: usize) -> Json {
+    let mut fields = vec![];
+    for module in &net.modules {
+        if let Json::Object(places) =
+            marking_json(net, module.id, &space.marking(graph, s, module.id))
+        {
+            fields.extend(places);
+        }
+    }
+    Json::Object(fields)
+}
+
+pub fn trace_json(net: &ModularPetrinet, graph: &Graph, space: &StateSpace, trace: &Trace) -> Json {
+    let states = trace.states();
+    let steps = states.iter().enumerate().map(|(i, &s)| {
+        let transition = trace
+            .steps
+            .get(i)
+            .map(|e| net.transition_name(e.module_id, e.transition_id));
+        Json::object([
+            ("state", s.into()),
+            ("marking", state_json(net, graph, space, s)),
+            ("transition", transition.into()),
+        ])
+    });
+    Json::object([
+        ("steps", Json::array(steps)),
+        ("loop_start", trace.loop_start.into()),
+    ])
+}
+
+fn classification_json(c: &Classification) -> Json {
+    Json::object([
+        ("ordinary", c.ordinary.into()),
+        ("state_machine", c.state_machine.into()),
+        ("marked_graph", c.marked_graph.into()),
+        ("free_choice", c.free_choice.into()),
+        ("extended_free_choice", c.extended_free_choice.into()),
+        ("asymmetric_choice", c.asymmetric_choice.into()),
+    ])
+}
+
+/// Modules with their places and intern transitions, then the interface transitions
+pub fn parse(
+    net: &ModularPetrinet,
+    classify_net: bool,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(TEXT_OR_JSON, "parse")?;
+    let start = net.intern_transition_start;
+    let interface = (0..start).map(|t_id| {
+        let modules = &net.extern_t_overview[t_id as usize];
+        let names = modules
+            .iter()
+            .map(|&m| net.modules[m as usize].name.as_str());
+        (net.transition_name(0, t_id), names.collect::<Vec<_>>())
+    });
+
+    if format == Format::Json {
+        let modules = net.modules.iter().map(|module| {
+            let marking = &net.markings[module.id as usize];
+            let places = module.places.iter().map(|p| p.name.as_str());
+            let intern = module.transitions[start as usize..]
+                .iter()
+                .map(|t| t.name.as_str());
+            let mut fields = vec![
+                ("name", module.name.as_str().into()),
+                ("places", Json::array(places)),
+                ("initial", marking_json(net, module.id, marking)),
+                ("transitions", Json::array(intern)),
+            ];
+            if classify_net {
+                let class = classify(&FlatNet::module(net, module.id));
+                fields.push(("class", classification_json(&class)));
+            }
+            Json::object(fields)
+        });
+        let interface = interface.map(|(name, modules)| {
+            Json::object([("name", name.into()), ("modules", Json::array(modules))])
+        });
+        let mut fields = vec![
+            ("modules", Json::array(modules)),
+            ("interface", Json::array(interface)),
+        ];
+        if classify_net {
+            let class = classify(&FlatNet::new(net));
+            fields.push(("class", classification_json(&class)));
+        }
+        writeln!(out, "{}", Json::object(fields))?;
+        return Ok(exit::OK);
+    }
+
+    for module in &net.modules {
+        let marking = &net.markings[module.id as usize];
+        let places = module
+            .places
+            .iter()
+            .map(|p| match marking.count(p.id) {
+                0 => p.name.clone(),
+                n => format!("{}({})", p.name, n),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "Module {}: {}", module.name, places.join(", "))?;
+        let intern = module.transitions[start as usize..]
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        writeln!(out, "  Transitions: {}", intern.join(", "))?;
+        if classify_net {
+            let class = classify(&FlatNet::module(net, module.id));
+            writeln!(out, "  Class: {}", class)?;
+        }
+    }
+    for (name, modules) in interface {
+        writeln!(out, "Interface {}: {}", name, modules.join(", "))?;
+    }
+    if classify_net {
+        writeln!(out, "Net: {}", classify(&FlatNet::new(net)))?;
+    }
+    Ok(exit::OK)
+}
+
+fn sync_dot(
+    net: &ModularPetrinet,
+    sync_graph: &[SyncMarking],
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    writeln!(out, "digraph sync {{")?;
+    for (id, node) in sync_graph.iter().enumerate() {
+        writeln!(
+            out,
+            "  s{} [label=\"{}\\n{:?}\"];",
+            id, id, node.segment_ids
+        )?;
+        for edge in &node.edges {
+            writeln!(
+                out,
+                "  s{} -> s{} [label=\"{}\"];",
+                id,
+                edge.sync_marking_id,
+                net.transition_name(0, edge.transition_id)
+            )?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+fn sync_json(net: &ModularPetrinet, sync_graph: &[SyncMarking]) -> Json {
+    Json::array(sync_graph.iter().map(|node| {
+        let edges = node.edges.iter().map(|e| {
+            Json::object([
+                ("transition", net.transition_name(0, e.transition_id).into()),
+                ("target", e.sync_marking_id.into()),
+            ])
+        });
+        Json::object([
+            ("segments", Json::array(node.segment_ids.iter().copied())),
+            ("edges", Json::array(edges)),
+        ])
+    }))
+}
+
+/// Build the sync graph and write it, exit INCOMPLETE after writing a partial graph
+pub fn graph(
+    net: &ModularPetrinet,
+    explore_args: &ExploreArgs,
+    symbolic: bool,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(&[Format::Text, Format::Json, Format::Dot], "graph")?;
+    if symbolic {
+        explore_args.unsupported("--symbolic", true, true)?;
+        if explore_args.max_segment_markings.is_some() {
+            return Err(CliError::new(
+                exit::USAGE,
+                "--max-segment-markings does not apply to --symbolic",
+            ));
+        }
+        let graph = build_symbolic_graph_with(net, &explore_args.options())
+            .map_err(|e| CliError::new(exit::INVALID, e))?;
+        match format {
+            Format::Text => graph.write(net, out)?,
+            Format::Dot => sync_dot(net, &graph.sync_graph, out)?,
+            Format::Csv => unreachable!("csv is rejected above"),
+            Format::Json => {
+                let segments = net.modules.iter().map(|module| {
+                    let counts = (0..graph.segments[module.id as usize].len() as u32)
+                        .map(|seg_id| graph.marking_count(module.id, seg_id));
+                    Json::object([
+                        ("name", module.name.as_str().into()),
+                        ("markings", Json::array(counts)),
+                    ])
+                });
+                let json = Json::object([
+                    ("complete", graph.incomplete.is_none().into()),
+                    ("sync_nodes", sync_json(net, &graph.sync_graph)),
+                    ("modules", Json::array(segments)),
+                    ("bdd_nodes", graph.bdd.size().into()),
+                ]);
+                writeln!(out, "{}", json)?;
+            }
+        }
+        out.flush()?;
+        return match graph.incomplete {
+            None => Ok(exit::OK),
+            Some(limit) => Err(CliError::new(
+                exit::INCOMPLETE,
+                format!("exploration incomplete, limit reached: {:?}", limit),
+            )),
+        };
+    }
+
+    let (graph, _) = explore(net, explore_args);
+    match format {
+        Format::Text => graph.write(net, out)?,
+        Format::Dot => sync_dot(net, &graph.sync_graph, out)?,
+        Format::Csv => unreachable!("csv is rejected above"),
+        Format::Json => {
+            let modules = net.modules.iter().map(|module| {
+                let storage = &graph.segment_storage[module.id as usize].0;
+                let segments = storage.iter().map(|(segment, extern_edges)| {
+                    let markings = segment.markings.iter().map(|m| {
+                        let edges = m.edges.iter().map(|e| {
+                            let name = net.transition_name(module.id, e.transition_id);
+                            Json::object([
+                                ("transition", name.into()),
+                                ("target", e.graph_marking_id.into()),
+                            ])
+                        });
+                        let marking = segment.marking(m.id);
+                        Json::object([
+                            ("id", m.id.into()),
+                            ("marking", marking_json(net, module.id, &marking)),
+                            ("edges", Json::array(edges)),
+                        ])
+                    });
+                    let extern_edges = extern_edges.iter().map(|(t_id, pairs)| {
+                        let pairs = pairs
+                            .iter()
+                            .map(|&(a, b)| Json::array([a, b]))
+                            .collect::<Vec<_>>();
+                        Json::object([
+                            ("transition", net.transition_name(0, *t_id).into()),
+                            ("edges", Json::Array(pairs)),
+                        ])
+                    });
+                    Json::object([
+                        ("markings", Json::array(markings)),
+                        ("extern", Json::array(extern_edges)),
+                    ])
+                });
+                Json::object([
+                    ("name", module.name.as_str().into()),
+                    ("segments", Json::array(segments)),
+                ])
+            });
+            let json = Json::object([
+                ("complete", graph.incomplete.is_none().into()),
+                ("sync_nodes", sync_json(net, &graph.sync_graph)),
+                ("modules", Json::array(modules)),
+            ]);
+            writeln!(out, "{}", json)?;
+        }
+    }
+    out.flush()?;
+    complete(&graph)?;
+    Ok(exit::OK)
+}
+
+/// Sizes of the sync graph, the expanded state space only for a complete graph
+pub fn stats(
+    net: &ModularPetrinet,
+    explore_args: &ExploreArgs,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(TEXT_OR_JSON, "stats")?;
+    let (graph, stats) = explore(net, explore_args);
+    let states = graph
+        .incomplete
+        .is_none()
+        .then(|| expand(net, &graph).states.len());
+
+    if format == Format::Json {
+        let segments = net.modules.iter().zip(&stats.segments).map(|(module, &n)| {
+            Json::object([
+                ("name", module.name.as_str().into()),
+                ("segments", n.into()),
+            ])
+        });
+        let json = Json::object([
+            ("complete", graph.incomplete.is_none().into()),
+            ("sync_nodes", stats.sync_nodes.into()),
+            ("modules", Json::array(segments)),
+            ("markings", stats.markings.into()),
+            ("states", states.into()),
+            ("seconds", stats.elapsed.as_secs_f64().into()),
+        ]);
+        writeln!(out, "{}", json)?;
+    } else {
+        writeln!(out, "Sync Nodes: {}", stats.sync_nodes)?;
+        for (module, segments) in net.modules.iter().zip(&stats.segments) {
+            writeln!(out, "Segments {}: {}", module.name, segments)?;
+        }
+        writeln!(out, "Markings: {}", stats.markings)?;
+        if let Some(states) = states {
+            writeln!(out, "States: {}", states)?;
+        }
+        writeln!(out, "Time: {:.3?}", stats.elapsed)?;
+    }
+    out.flush()?;
+    complete(&graph)?;
+    Ok(exit::OK)
+}
+
+/// exactly one of the formulas
+#[derive(Args, Debug, Clone)]
+#[command(group(ArgGroup::new("formula").required(true)))]
+pub struct QueryArgs {
+    /// CTL formula, e.g. "AG !deadlock"
+    #[arg(long, group = "formula")]
+    ctl: Option<String>,
+
+    /// LTL formula, e.g. "G F enabled(t1)"
+    #[arg(long, group = "formula")]
+    ltl: Option<String>,
+
+    /// marking constraints, e.g. "p1 >= 1 & q2 = 0", checked with the state equation first
+    #[arg(long, group = "formula")]
+    reachable: Option<String>,
+}
+
+enum Query {
+    Ctl(ctl::Formula),
+    Ltl(ltl::Formula),
+}
+
+fn write_result(
+    formula: &str,
+    holds: bool,
+    method: &str,
+    trace: Option<Json>,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    if format == Format::Json {
+        let json = Json::object([
+            ("formula", formula.into()),
+            ("holds", holds.into()),
+            ("method", method.into()),
+            ("trace", trace.into()),
+        ]);
+        writeln!(out, "{}", json)?;
+    } else {
+        writeln!(out, "{}: {} ({})", formula, holds, method)?;
+    }
+    Ok(())
+}
+
+/// Check a formula in the initial state, exit VIOLATED if it does not hold
+pub fn query(
+    net: &ModularPetrinet,
+    query_args: &QueryArgs,
+    explore_args: &ExploreArgs,
+    with_trace: bool,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(TEXT_OR_JSON, "query")?;
+    explore_args.unsupported("query", true, false)?;
+    let invalid = |e: String| CliError::new(exit::INVALID, e);
+    let (text, query) = match query_args {
+        QueryArgs { ctl: Some(f), .. } => (f.clone(), Query::Ctl(ctl::parse(f).map_err(invalid)?)),
+        QueryArgs { ltl: Some(f), .. } => (f.clone(), Query::Ltl(ltl::parse(f).map_err(invalid)?)),
+        QueryArgs {
+            reachable: Some(target),
+            ..
+        } => {
+            let text = format!("EF ({})", target);
+            let target = parse_target(target).map_err(invalid)?;
+            if check_target(net, &target).map_err(invalid)? == Verdict::Unreachable {
+                write_result(&text, false, "state equation", None, format, out)?;
+                return Ok(exit::VIOLATED);
+            }
+            (
+                text.clone(),
+                Query::Ctl(ctl::parse(&text).map_err(invalid)?),
+            )
+        }
+        _ => unreachable!("clap requires one formula"),
+    };
+    if explore_args.symmetry {
+        // unknown names are invalid, names in a class are a usage error
+        let preserved = |classes: &[Vec<ModuleId>]| match &query {
+            Query::Ctl(f) => f.preserved(net, classes),
+            Query::Ltl(f) => f.preserved(net, classes),
+        };
+        preserved(&[]).map_err(invalid)?;
+        preserved(&symmetric_modules(net)).map_err(|e| CliError::new(exit::USAGE, e))?;
+    }
+
+    let (graph, _) = explore(net, explore_args);
+    complete(&graph)?;
+    // CTL expands the states it needs, LTL the whole space
+    let (result, space) = match &query {
+        Query::Ctl(f) => {
+            let mut space = StateSpace::initial(&graph);
+            (ctl::check(net, &graph, &mut space, f), space)
+        }
+        Query::Ltl(f) => {
+            let space = expand(net, &graph);
+            (ltl::check(net, &graph, &space, f), space)
+        }
+    };
+    let CheckResult { holds, trace } = result.map_err(invalid)?;
+
+    let trace = trace.filter(|_| with_trace);
+    let trace_json = trace.as_ref().map(|t| trace_json(net, &graph, &space, t));
+    write_result(&text, holds, "state space", trace_json, format, out)?;
+    if let (Some(trace), Format::Text) = (trace, format) {
+        trace.write(net, &graph, &space, out)?;
+    }
+    Ok(if holds { exit::OK } else { exit::VIOLATED })
+}
+
+/// Sizes and time of the sync graph and of the reachability graph of the flat net,
+/// exit VIOLATED if they differ in the reachable markings
+pub fn compare(
+    net: &ModularPetrinet,
+    explore_args: &ExploreArgs,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(TEXT_OR_JSON, "compare")?;
+    explore_args.unsupported("compare", true, true)?;
+    let (graph, stats) = explore(net, explore_args);
+    complete(&graph)?;
+    let space = expand(net, &graph);
+    let flat = FlatNet::new(net);
+    let markings = distinct_markings(&flat, &graph, &space);
+
+    let start = Instant::now();
+    let flat_graph = build_flat_graph(&flat, &explore_args.options());
+    let flat_time = start.elapsed();
+    complete_flat(&flat_graph.incomplete)?;
+    let equal = markings.len() == flat_graph.markings.len()
+        && flat_graph.markings.iter().all(|m| markings.contains(m));
+
+    let segments = stats.segments.iter().sum::<usize>();
+    if format == Format::Json {
+        let json = Json::object([
+            (
+                "sync",
+                Json::object([
+                    ("sync_nodes", stats.sync_nodes.into()),
+                    ("segments", segments.into()),
+                    ("markings", stats.markings.into()),
+                    ("states", space.states.len().into()),
+                    ("distinct_markings", markings.len().into()),
+                    ("seconds", stats.elapsed.as_secs_f64().into()),
+                ]),
+            ),
+            (
+                "flat",
+                Json::object([
+                    ("transitions", flat.transitions.len().into()),
+                    ("markings", flat_graph.markings.len().into()),
+                    ("edges", flat_graph.edge_count().into()),
+                    ("seconds", flat_time.as_secs_f64().into()),
+                ]),
+            ),
+            ("equal", equal.into()),
+        ]);
+        writeln!(out, "{}", json)?;
+    } else {
+        writeln!(out, "Sync Nodes: {}", stats.sync_nodes)?;
+        writeln!(out, "Segments: {}", segments)?;
+        writeln!(out, "Local Markings: {}", stats.markings)?;
+        writeln!(out, "States: {}", space.states.len())?;
+        writeln!(out, "Distinct Markings: {}", markings.len())?;
+        writeln!(out, "Sync Time: {:.3?}", stats.elapsed)?;
+        writeln!(out, "Flat Markings: {}", flat_graph.markings.len())?;
+        writeln!(out, "Flat Edges: {}", flat_graph.edge_count())?;
+        writeln!(out, "Flat Time: {:.3?}", flat_time)?;
+        writeln!(out, "Equal: {}", equal)?;
+    }
+    Ok(if equal { exit::OK } else { exit::VIOLATED })
+}
+
+fn complete_flat(incomplete: &Option<Limit>) -> Result<(), CliError> {
+    match incomplete {
+        None => Ok(()),
+        Some(limit) => Err(CliError::new(
+            exit::INCOMPLETE,
+            format!("flat exploration incomplete, limit reached: {:?}", limit),
+        )),
+    }
+}
@@ -0,0 +1,37 @@
+use std::{fs, io::Write};
+
+use pnawir::parser::format_input;
+
+use super::{exit, output::Format, parse_raw, read, CliError};
+
+/// Format every net, print it, rewrite the file with --write
+/// or only list the files which are not formatted with --check
+pub fn fmt(
+    files: &[String],
+    write: bool,
+    check: bool,
+    format: Format,
+    out: &mut dyn Write,
+) -> Result<i32, CliError> {
+    format.require(&[Format::Text], "fmt")?;
+    let mut code = exit::OK;
+    for file in files {
+        let input = read(file)?;
+        let text = format_input::fmt(&parse_raw(file, &input)?);
+        if check {
+            if text != input {
+                writeln!(out, "{}", file)?;
+                code = exit::VIOLATED;
+            }
+        } else if write && file != "-" {
+            if text != input {
+                fs::write(file, &text)
+                    .map_err(|e| CliError::new(exit::OUTPUT, format!("{}: {}", file, e)))?;
+                log::info!("formatted {}", file);
+            }
+        } else {
+            write!(out, "{}", text)?;
+        }
+    }
+    Ok(code)
+}
@@ -0,0 +1,62 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes the records of the library and the CLI to stderr
+struct StderrLogger;
+
+static LOGGER: StderrLogger = StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let level = record.level().as_str().to_lowercase();
+            eprintln!("{}: {}", level, record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// warn by default, every -v one level more and every -q one level less
+pub fn init(verbose: u8, quiet: u8) {
+    log::set_logger(&LOGGER).expect("logger is set once");
+    log::set_max_level(level(verbose, quiet));
+}
+
+fn level(verbose: u8, quiet: u8) -> LevelFilter {
+    let levels = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let level = (2 + verbose as usize).saturating_sub(quiet as usize);
+    levels[level.min(levels.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LevelFilter;
+
+    use super::level;
+
+    #[test]
+    fn test_level() {
+        assert_eq!(level(0, 0), LevelFilter::Warn);
+        assert_eq!(level(1, 0), LevelFilter::Info);
+        assert_eq!(level(2, 0), LevelFilter::Debug);
+        assert_eq!(level(3, 0), LevelFilter::Trace);
+        assert_eq!(level(9, 0), LevelFilter::Trace);
+        assert_eq!(level(0, 1), LevelFilter::Error);
+        assert_eq!(level(0, 2), LevelFilter::Off);
+        assert_eq!(level(0, 9), LevelFilter::Off);
+        // -v and -q cancel out
+        assert_eq!(level(1, 1), LevelFilter::Warn);
+        assert_eq!(level(3, 2), LevelFilter::Info);
+    }
+}
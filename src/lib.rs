@@ -1,3 +1,8 @@
+pub mod model_checking;
 pub mod modular_net;
 pub mod parser;
+pub mod structural;
 pub mod sync_reachability_graph;
+
+#[cfg(test)]
+mod testing;
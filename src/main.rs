@@ -1,41 +1,345 @@
-use std::fs::{self};
+use std::{io::Write, path::PathBuf, process};
 
-use clap::Parser;
+use clap::{ArgAction, Parser, Subcommand};
 
-use pnawir::{
-    self, parser::transform_input,
-    sync_reachability_graph::build_graph::build_sync_reachability_graph,
+use cli::{
+    batch,
+    check::{self, Property},
+    commands::{self, QueryArgs},
+    format, load, logger,
+    output::{self, Format},
+    CliError, ExploreArgs,
 };
+
+mod cli;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-
+#[command(after_help = "Exit codes: 0 ok, 1 property violated, 2 usage error, \
+3 invalid net or formula, 4 exploration incomplete, 5 net not readable, 6 output not writable")]
 struct Cli {
-    #[arg(short, long, value_parser)]
-    filename: String,
+    #[command(subcommand)]
+    command: Command,
+
+    /// output format, dot only for graph
+    #[arg(long, value_enum, global = true, default_value_t = Format::Text)]
+    format: Format,
+
+    /// write the output to this file instead of stdout
+    #[arg(short, long, global = true)]
+    output: Option<PathBuf>,
+
+    /// more diagnostics on stderr, up to -vvv
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// fewer diagnostics on stderr, -qq for none at all
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    quiet: u8,
 }
 
-fn main() {
-    let s_def = include_str!("../examples/ba2022/P002.pnawir");
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// validate the net and print its modules
+    Parse {
+        /// the net, - for stdin
+        filename: String,
 
-    let args = Cli::parse();
+        /// print the structural class of every module and the composed net
+        #[arg(short, long)]
+        classify: bool,
+    },
+    /// build the sync reachability graph
+    Graph {
+        /// the net, - for stdin
+        filename: String,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+
+        /// store the segments as Bdds, only for 1-safe nets; the graph is only written out,
+        /// stats, query and check always use the explicit graph
+        #[arg(long)]
+        symbolic: bool,
+    },
+    /// sizes of the sync reachability graph and of the expanded state space
+    Stats {
+        /// the net, - for stdin
+        filename: String,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+    },
+    /// check a CTL or LTL formula or the reachability of a marking
+    Query {
+        /// the net, - for stdin
+        filename: String,
+
+        #[command(flatten)]
+        query: QueryArgs,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+
+        /// print a witness or counterexample
+        #[arg(long)]
+        trace: bool,
+    },
+    /// check deadlock freedom, liveness and boundedness, all without a property
+    Check {
+        /// the net, - for stdin
+        filename: String,
+
+        #[arg(value_enum)]
+        properties: Vec<Property>,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+
+        /// print a path to a deadlock
+        #[arg(long)]
+        trace: bool,
+    },
+    /// compare the sync graph with the reachability graph of the flat net
+    Compare {
+        /// the net, - for stdin
+        filename: String,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+    },
+    /// summary table of several nets, format text, csv or json
+    Batch {
+        /// nets or directories of .pnawir files
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        #[command(flatten)]
+        explore: ExploreArgs,
+    },
+    /// print the nets in canonical form
+    Fmt {
+        /// the nets, - for stdin
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// rewrite the files in place
+        #[arg(short, long, conflicts_with = "check")]
+        write: bool,
+
+        /// only list the files which are not formatted, exit 1 if there are any
+        #[arg(long)]
+        check: bool,
+    },
+}
 
-    let input;
-    match fs::read_to_string(&args.filename) {
-        Ok(s) => input = s,
-        Err(_ee) => {
-            println!("File: {} not found", &args.filename);
-            println!("using default template");
-            input = s_def.to_string();
-            // panic!("{}", _e);
+fn run(args: &Cli) -> Result<i32, CliError> {
+    let format = args.format;
+    let filename = match &args.command {
+        Command::Parse { filename, .. }
+        | Command::Graph { filename, .. }
+        | Command::Stats { filename, .. }
+        | Command::Query { filename, .. }
+        | Command::Check { filename, .. }
+        | Command::Compare { filename, .. } => filename,
+        Command::Batch { paths, explore } => {
+            let mut out = output::open(args.output.as_deref())?;
+            let code = batch::batch(paths, explore, format, &mut out)?;
+            out.flush()?;
+            return Ok(code);
         }
+        Command::Fmt {
+            files,
+            write,
+            check,
+        } => {
+            let mut out = output::open(args.output.as_deref())?;
+            let code = format::fmt(files, *write, *check, format, &mut out)?;
+            out.flush()?;
+            return Ok(code);
+        }
+    };
+    let net = load(filename)?;
+    let mut out = output::open(args.output.as_deref())?;
+
+    let code = match &args.command {
+        Command::Parse { classify, .. } => commands::parse(&net, *classify, format, &mut out)?,
+        Command::Graph {
+            explore, symbolic, ..
+        } => commands::graph(&net, explore, *symbolic, format, &mut out)?,
+        Command::Stats { explore, .. } => commands::stats(&net, explore, format, &mut out)?,
+        Command::Query {
+            query,
+            explore,
+            trace,
+            ..
+        } => commands::query(&net, query, explore, *trace, format, &mut out)?,
+        Command::Check {
+            properties,
+            explore,
+            trace,
+            ..
+        } => check::check(&net, properties, explore, *trace, format, &mut out)?,
+        Command::Compare { explore, .. } => commands::compare(&net, explore, format, &mut out)?,
+        Command::Batch { .. } | Command::Fmt { .. } => unreachable!("handled above"),
+    };
+    out.flush()?;
+    Ok(code)
+}
+
+fn main() {
+    let args = Cli::parse();
+    logger::init(args.verbose, args.quiet);
+    match run(&args) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            log::error!("{}", e);
+            process::exit(e.code);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use clap::Parser;
+
+    use super::{cli::exit, run, Cli};
+
+    /// exit code of the command line, the output goes to a temporary file
+    fn code(args: &[&str]) -> i32 {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let output = env::temp_dir().join(format!("pnawir-{}-{}.out", std::process::id(), count));
+        let output = output.to_string_lossy();
+        let mut line = vec!["pnawir"];
+        line.extend(args);
+        line.extend(["-o", &output]);
+        let code = run(&Cli::try_parse_from(line).unwrap()).unwrap_or_else(|e| e.code);
+        let _ = fs::remove_file(&*output);
+        code
+    }
+
+    #[test]
+    fn test_query() {
+        let net = "examples/symmetry/S001.pnawir";
+        assert_eq!(code(&["query", net, "--ctl", "AG !deadlock"]), exit::OK);
+        assert_eq!(code(&["query", net, "--ctl", "AG m = 1"]), exit::VIOLATED);
+        assert_eq!(code(&["query", net, "--ltl", "G F m = 1"]), exit::OK);
+        assert_eq!(code(&["query", net, "--ctl", "AG x = 1"]), exit::INVALID);
+        assert_eq!(code(&["query", net, "--ctl", "AG"]), exit::INVALID);
+        assert_eq!(
+            code(&["query", "missing.pnawir", "--ctl", "true"]),
+            exit::INPUT
+        );
+        assert_eq!(
+            code(&["query", net, "--ctl", "AG m = 1", "--max-sync-nodes", "1"]),
+            exit::INCOMPLETE
+        );
+
+        // the same answer with symmetry, unless the formula tells the workers apart
+        let formula = "EF (i1 = 1 & w2 = 1 & d3 = 1)";
+        assert_eq!(code(&["query", net, "--ctl", formula]), exit::OK);
+        assert_eq!(
+            code(&["query", net, "--ctl", formula, "--symmetry"]),
+            exit::USAGE
+        );
+        assert_eq!(
+            code(&["query", net, "--ctl", "AG m = 1", "--symmetry"]),
+            exit::VIOLATED
+        );
+        assert_eq!(
+            code(&["query", net, "--ctl", "AG y = 1", "--symmetry"]),
+            exit::INVALID
+        );
+        assert_eq!(
+            code(&[
+                "query",
+                net,
+                "--ctl",
+                "AG !deadlock",
+                "--reduction",
+                "deadlocks"
+            ]),
+            exit::USAGE
+        );
     }
 
-    // parse net
-    let (_, raw_parser_input) = pnawir::parser::parse_input::parse(&input[..]).unwrap();
-    // dbg!(&raw_parser_input);
-    let modular_net = transform_input::transform(raw_parser_input);
-    // dbg!(&modular_net);
+    #[test]
+    fn test_check() {
+        let net = "examples/symmetry/S001.pnawir";
+        assert_eq!(code(&["check", net]), exit::OK);
+        assert_eq!(code(&["check", net, "deadlock", "--symmetry"]), exit::OK);
+        assert_eq!(
+            code(&["check", net, "deadlock", "--reduction", "interface"]),
+            exit::OK
+        );
+        assert_eq!(code(&["check", net, "liveness", "--symmetry"]), exit::USAGE);
+        assert_eq!(
+            code(&["check", net, "--reduction", "deadlocks"]),
+            exit::USAGE
+        );
+        assert_eq!(code(&["check", net, "--format", "dot"]), exit::USAGE);
+
+        let net = "examples/templates/philosophers.pnawir";
+        assert_eq!(code(&["check", net, "deadlock"]), exit::VIOLATED);
+        assert_eq!(
+            code(&["check", net, "deadlock", "--reduction", "deadlocks"]),
+            exit::VIOLATED
+        );
 
-    let graph = build_sync_reachability_graph(&modular_net);
-    graph.print(&modular_net);
+        // t puts a token on q every time it fires, no invariant covers q
+        let net = env::temp_dir().join(format!("pnawir-unbounded-{}.pnawir", std::process::id()));
+        let input = "{\n    t: M1.p -> M1.p, M2.q\n}\n\nM1 {\n    p(1)\n}\n\nM2 {\n    q\n}\n";
+        fs::write(&net, input).unwrap();
+        let net = net.to_string_lossy();
+        assert_eq!(code(&["check", &net, "boundedness"]), exit::VIOLATED);
+        assert_eq!(
+            code(&["check", &net, "boundedness", "--timeout", "0"]),
+            exit::INCOMPLETE
+        );
+        fs::remove_file(&*net).unwrap();
+    }
+
+    #[test]
+    fn test_graph() {
+        let net = "examples/symmetry/S001.pnawir";
+        assert_eq!(code(&["graph", net, "--symbolic"]), exit::OK);
+        assert_eq!(
+            code(&["graph", net, "--symbolic", "--max-sync-nodes", "2"]),
+            exit::INCOMPLETE
+        );
+        assert_eq!(
+            code(&["graph", net, "--symbolic", "--timeout", "0"]),
+            exit::INCOMPLETE
+        );
+        assert_eq!(
+            code(&["graph", net, "--symbolic", "--symmetry"]),
+            exit::USAGE
+        );
+        assert_eq!(
+            code(&["graph", net, "--symbolic", "--max-segment-markings", "2"]),
+            exit::USAGE
+        );
+        assert_eq!(code(&["graph", net, "--symmetry"]), exit::OK);
+    }
+
+    #[test]
+    fn test_compare() {
+        let net = "examples/symmetry/S001.pnawir";
+        assert_eq!(code(&["compare", net]), exit::OK);
+        assert_eq!(code(&["compare", net, "--symmetry"]), exit::USAGE);
+        assert_eq!(
+            code(&["compare", net, "--reduction", "interface"]),
+            exit::USAGE
+        );
+        assert_eq!(
+            code(&["compare", net, "--max-sync-nodes", "1"]),
+            exit::INCOMPLETE
+        );
+    }
 }
@@ -0,0 +1,5 @@
+pub mod buchi;
+pub mod ctl;
+pub mod ltl;
+pub mod proposition;
+pub mod trace;
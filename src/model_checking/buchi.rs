@@ -0,0 +1,191 @@
+use std::collections::BTreeSet;
+
+/// LTL formula in negation normal form
+/// propositions are indexes, Lit(p, false) is the negated proposition p
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Nnf {
+    True,
+    False,
+    Lit(usize, bool),
+    And(Box<Nnf>, Box<Nnf>),
+    Or(Box<Nnf>, Box<Nnf>),
+    Next(Box<Nnf>),
+    Until(Box<Nnf>, Box<Nnf>),
+    Release(Box<Nnf>, Box<Nnf>),
+}
+
+/// Generalized Büchi automaton, the labels are on the nodes
+#[derive(Debug)]
+pub struct Buchi {
+    /// literals that have to hold when the node is entered
+    pub labels: Vec<Vec<(usize, bool)>>,
+    pub successors: Vec<Vec<usize>>,
+    pub initial: Vec<usize>,
+    /// one set per until subformula, an accepting run visits every set infinitely often
+    pub accepting: Vec<Vec<bool>>,
+}
+
+const INIT: usize = usize::MAX;
+
+struct TableauNode {
+    incoming: BTreeSet<usize>,
+    new: BTreeSet<Nnf>,
+    old: BTreeSet<Nnf>,
+    next: BTreeSet<Nnf>,
+}
+
+/// Tableau construction after Gerth, Peled, Vardi and Wolper
+pub fn build(formula: &Nnf) -> Buchi {
+    let mut nodes: Vec<TableauNode> = vec![];
+    let mut stack = vec![TableauNode {
+        incoming: BTreeSet::from([INIT]),
+        new: BTreeSet::from([formula.clone()]),
+        old: BTreeSet::new(),
+        next: BTreeSet::new(),
+    }];
+
+    while let Some(mut node) = stack.pop() {
+        let Some(eta) = node.new.pop_first() else {
+            // fully expanded, merge with an equal node or create successor
+            if let Some(existing) = nodes
+                .iter_mut()
+                .find(|n| n.old == node.old && n.next == node.next)
+            {
+                existing.incoming.extend(node.incoming);
+            } else {
+                let successor = TableauNode {
+                    incoming: BTreeSet::from([nodes.len()]),
+                    new: node.next.clone(),
+                    old: BTreeSet::new(),
+                    next: BTreeSet::new(),
+                };
+                nodes.push(node);
+                stack.push(successor);
+            }
+            continue;
+        };
+        if node.old.contains(&eta) {
+            stack.push(node);
+            continue;
+        }
+
+        match &eta {
+            Nnf::True => stack.push(node),
+            Nnf::False => {}
+            Nnf::Lit(p, b) => {
+                if !node.old.contains(&Nnf::Lit(*p, !b)) {
+                    node.old.insert(eta);
+                    stack.push(node);
+                }
+            }
+            Nnf::And(a, b) => {
+                add_new(&mut node, a);
+                add_new(&mut node, b);
+                node.old.insert(eta);
+                stack.push(node);
+            }
+            Nnf::Next(a) => {
+                node.next.insert(*a.clone());
+                node.old.insert(eta);
+                stack.push(node);
+            }
+            Nnf::Or(a, b) | Nnf::Until(a, b) | Nnf::Release(a, b) => {
+                let mut first = TableauNode {
+                    incoming: node.incoming.clone(),
+                    new: node.new.clone(),
+                    old: node.old.clone(),
+                    next: node.next.clone(),
+                };
+                let mut second = node;
+                match &eta {
+                    Nnf::Or(_, _) => {
+                        add_new(&mut first, a);
+                        add_new(&mut second, b);
+                    }
+                    // a U b = b | (a & X(a U b))
+                    Nnf::Until(_, _) => {
+                        add_new(&mut first, a);
+                        first.next.insert(eta.clone());
+                        add_new(&mut second, b);
+                    }
+                    // a R b = b & (a | X(a R b))
+                    _ => {
+                        add_new(&mut first, b);
+                        first.next.insert(eta.clone());
+                        add_new(&mut second, a);
+                        add_new(&mut second, b);
+                    }
+                }
+                first.old.insert(eta.clone());
+                second.old.insert(eta);
+                stack.push(first);
+                stack.push(second);
+            }
+        }
+    }
+
+    let mut untils = vec![];
+    collect_untils(formula, &mut untils);
+
+    let mut buchi = Buchi {
+        labels: vec![],
+        successors: vec![vec![]; nodes.len()],
+        initial: vec![],
+        accepting: vec![],
+    };
+    for (id, node) in nodes.iter().enumerate() {
+        buchi.labels.push(
+            node.old
+                .iter()
+                .filter_map(|f| match f {
+                    Nnf::Lit(p, b) => Some((*p, *b)),
+                    _ => None,
+                })
+                .collect(),
+        );
+        for &i in &node.incoming {
+            if i == INIT {
+                buchi.initial.push(id);
+            } else {
+                buchi.successors[i].push(id);
+            }
+        }
+    }
+    for until in &untils {
+        let Nnf::Until(_, b) = until else {
+            unreachable!()
+        };
+        buchi.accepting.push(
+            nodes
+                .iter()
+                .map(|n| !n.old.contains(until) || n.old.contains(b))
+                .collect(),
+        );
+    }
+
+    buchi
+}
+
+fn add_new(node: &mut TableauNode, f: &Nnf) {
+    if !node.old.contains(f) {
+        node.new.insert(f.clone());
+    }
+}
+
+fn collect_untils(formula: &Nnf, untils: &mut Vec<Nnf>) {
+    match formula {
+        Nnf::True | Nnf::False | Nnf::Lit(_, _) => {}
+        Nnf::Next(a) => collect_untils(a, untils),
+        Nnf::And(a, b) | Nnf::Or(a, b) | Nnf::Release(a, b) => {
+            collect_untils(a, untils);
+            collect_untils(b, untils);
+        }
+        Nnf::Until(a, b) => {
+            if !untils.contains(formula) {
+                untils.push(formula.clone());
+            }
+            collect_untils(a, untils);
+            collect_untils(b, untils);
+        }
+    }
+}
@@ -0,0 +1,685 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::char;
+use nom::combinator::{map, value};
+use nom::multi::many0;
+use nom::sequence::{delimited, preceded, tuple};
+use nom::IResult;
+
+use crate::{
+    modular_net::{ModularPetrinet, ModuleId},
+    parser::parse_input::ws,
+    sync_reachability_graph::{
+        graph::Graph,
+        state_space::{StateEdge, StateId, StateSpace},
+    },
+};
+
+use super::{
+    proposition::{atom, keyword, Atom, Resolved},
+    trace::{CheckResult, Trace},
+};
+
+/// CTL formula
+/// paths are maximal, a deadlock state has only the path that stays in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Formula {
+    True,
+    False,
+    Atom(Atom),
+    Not(Box<Formula>),
+    And(Box<Formula>, Box<Formula>),
+    Or(Box<Formula>, Box<Formula>),
+    Implies(Box<Formula>, Box<Formula>),
+    ExistsNext(Box<Formula>),
+    ExistsFinally(Box<Formula>),
+    ExistsGlobally(Box<Formula>),
+    ExistsUntil(Box<Formula>, Box<Formula>),
+    AllNext(Box<Formula>),
+    AllFinally(Box<Formula>),
+    AllGlobally(Box<Formula>),
+    AllUntil(Box<Formula>, Box<Formula>),
+}
+
+impl Formula {
+    /// the value does not change under a permutation within the classes of identical modules
+    pub fn preserved(
+        &self,
+        net: &ModularPetrinet,
+        classes: &[Vec<ModuleId>],
+    ) -> Result<(), String> {
+        match self {
+            Formula::True | Formula::False => Ok(()),
+            Formula::Atom(a) => a.preserved(net, classes),
+            Formula::Not(a)
+            | Formula::ExistsNext(a)
+            | Formula::ExistsFinally(a)
+            | Formula::ExistsGlobally(a)
+            | Formula::AllNext(a)
+            | Formula::AllFinally(a)
+            | Formula::AllGlobally(a) => a.preserved(net, classes),
+            Formula::And(a, b)
+            | Formula::Or(a, b)
+            | Formula::Implies(a, b)
+            | Formula::ExistsUntil(a, b)
+            | Formula::AllUntil(a, b) => {
+                a.preserved(net, classes)?;
+                b.preserved(net, classes)
+            }
+        }
+    }
+}
+
+/// Check the formula in the initial state
+/// evaluated locally from the initial state, only the states it reaches are expanded from the
+/// sync graph into the space; the value of every subformula is kept per state
+/// the trace is a witness for a satisfied E formula or a counterexample for a violated A formula
+pub fn check(
+    net: &ModularPetrinet,
+    graph: &Graph,
+    space: &mut StateSpace,
+    formula: &Formula,
+) -> Result<CheckResult, String> {
+    formula.preserved(net, &graph.symmetry)?;
+    let mut atoms = HashMap::new();
+    bind(net, formula, &mut atoms)?;
+    let mut checker = Checker {
+        net,
+        graph,
+        space,
+        atoms,
+        memo: HashMap::new(),
+    };
+    let holds = checker.sat(formula, 0)?;
+    let trace = checker.explain(0, formula, holds)?;
+    Ok(CheckResult { holds, trace })
+}
+
+/// resolve the atoms of the formula, by their address
+fn bind(
+    net: &ModularPetrinet,
+    formula: &Formula,
+    atoms: &mut HashMap<usize, Resolved>,
+) -> Result<(), String> {
+    match formula {
+        Formula::True | Formula::False => Ok(()),
+        Formula::Atom(a) => {
+            atoms.insert(key(formula), a.resolve(net)?);
+            Ok(())
+        }
+        Formula::Not(a)
+        | Formula::ExistsNext(a)
+        | Formula::ExistsFinally(a)
+        | Formula::ExistsGlobally(a)
+        | Formula::AllNext(a)
+        | Formula::AllFinally(a)
+        | Formula::AllGlobally(a) => bind(net, a, atoms),
+        Formula::And(a, b)
+        | Formula::Or(a, b)
+        | Formula::Implies(a, b)
+        | Formula::ExistsUntil(a, b)
+        | Formula::AllUntil(a, b) => {
+            bind(net, a, atoms)?;
+            bind(net, b, atoms)
+        }
+    }
+}
+
+/// Subformula or its negation, the universal operators search for the negation
+#[derive(Clone, Copy)]
+struct Literal<'f> {
+    formula: &'f Formula,
+    positive: bool,
+}
+
+const TRUE: Literal<'static> = Literal {
+    formula: &Formula::True,
+    positive: true,
+};
+
+fn pos(formula: &Formula) -> Literal<'_> {
+    Literal {
+        formula,
+        positive: true,
+    }
+}
+
+fn neg(formula: &Formula) -> Literal<'_> {
+    Literal {
+        formula,
+        positive: false,
+    }
+}
+
+/// Formula whose value is decided by a search, it is found if the search succeeds
+/// the search stores the value in every state it decides
+#[derive(Clone, Copy)]
+struct Node {
+    key: usize,
+    found: bool,
+}
+
+/// subformulas are identified by their address, the formula outlives the checker
+fn key(formula: &Formula) -> usize {
+    formula as *const Formula as usize
+}
+
+struct Checker<'a> {
+    net: &'a ModularPetrinet,
+    graph: &'a Graph,
+    space: &'a mut StateSpace,
+    atoms: HashMap<usize, Resolved>,
+    /// (subformula, state) -> value
+    memo: HashMap<(usize, StateId), bool>,
+}
+
+impl Checker<'_> {
+    fn successors(&mut self, s: StateId) -> Vec<StateEdge> {
+        self.space.expand_state(self.net, self.graph, s).to_vec()
+    }
+
+    fn literal(&mut self, literal: Literal, s: StateId) -> Result<bool, String> {
+        Ok(self.sat(literal.formula, s)? == literal.positive)
+    }
+
+    /// value of the formula in the state
+    fn sat(&mut self, formula: &Formula, s: StateId) -> Result<bool, String> {
+        if let Some(&value) = self.memo.get(&(key(formula), s)) {
+            return Ok(value);
+        }
+        let node = |found| Node {
+            key: key(formula),
+            found,
+        };
+        let value = match formula {
+            Formula::True => true,
+            Formula::False => false,
+            Formula::Atom(_) => {
+                if self.atoms[&key(formula)] == Resolved::Deadlock {
+                    self.successors(s);
+                }
+                self.atoms[&key(formula)].holds(self.net, self.graph, self.space, s)
+            }
+            Formula::Not(a) => !self.sat(a, s)?,
+            Formula::And(a, b) => self.sat(a, s)? && self.sat(b, s)?,
+            Formula::Or(a, b) => self.sat(a, s)? || self.sat(b, s)?,
+            Formula::Implies(a, b) => !self.sat(a, s)? || self.sat(b, s)?,
+            Formula::ExistsNext(a) => self.next(s, pos(a))?.is_some(),
+            Formula::AllNext(a) => self.next(s, neg(a))?.is_none(),
+            Formula::ExistsFinally(a) => {
+                self.until(s, TRUE, &[pos(a)], Some(node(true)))?.is_some()
+            }
+            Formula::ExistsUntil(a, b) => self
+                .until(s, pos(a), &[pos(b)], Some(node(true)))?
+                .is_some(),
+            Formula::AllGlobally(a) => self.until(s, TRUE, &[neg(a)], Some(node(false)))?.is_none(),
+            Formula::ExistsGlobally(a) => self.globally(s, pos(a), Some(node(true)))?[&s],
+            Formula::AllFinally(a) => !self.globally(s, neg(a), Some(node(false)))?[&s],
+            Formula::AllUntil(a, b) => {
+                // !(E[!b U (!a & !b)] | EG !b)
+                self.until(s, neg(b), &[neg(a), neg(b)], None)?.is_none()
+                    && !self.globally(s, neg(b), None)?[&s]
+            }
+        };
+        self.memo.insert((key(formula), s), value);
+        Ok(value)
+    }
+
+    /// edge to a successor satisfying the literal
+    fn next(&mut self, s: StateId, a: Literal) -> Result<Option<StateEdge>, String> {
+        for edge in self.successors(s) {
+            if self.literal(a, edge.state_id)? {
+                return Ok(Some(edge));
+            }
+        }
+        Ok(None)
+    }
+
+    /// shortest path through a states to a state satisfying all of b, breadth first from s
+    /// with a node, states already decided for it end the search as well, the path states are
+    /// found and if there is none every visited state is not
+    fn until(
+        &mut self,
+        s: StateId,
+        a: Literal,
+        b: &[Literal],
+        node: Option<Node>,
+    ) -> Result<Option<Vec<StateEdge>>, String> {
+        let mut parent = HashMap::from([(s, None)]);
+        let mut to_explore = VecDeque::from([s]);
+        while let Some(c) = to_explore.pop_front() {
+            let decided = node
+                .filter(|_| c != s)
+                .and_then(|n| self.memo.get(&(n.key, c)).map(|&v| v == n.found));
+            let found = match decided {
+                Some(found) => found,
+                None => self.all(b, c)?,
+            };
+            if found {
+                let mut steps: Vec<StateEdge> = vec![];
+                let mut cur = c;
+                while let Some(Some((prev, edge))) = parent.get(&cur) {
+                    steps.push(*edge);
+                    cur = *prev;
+                }
+                steps.reverse();
+                if let Some(n) = node {
+                    for t in steps.iter().map(|e| e.state_id).chain([s]) {
+                        self.memo.insert((n.key, t), n.found);
+                    }
+                }
+                return Ok(Some(steps));
+            }
+            if decided.is_some() || !self.literal(a, c)? {
+                continue;
+            }
+            for edge in self.successors(c) {
+                if let Entry::Vacant(e) = parent.entry(edge.state_id) {
+                    e.insert(Some((c, edge)));
+                    to_explore.push_back(edge.state_id);
+                }
+            }
+        }
+        if let Some(n) = node {
+            for &t in parent.keys() {
+                self.memo.insert((n.key, t), !n.found);
+            }
+        }
+        Ok(None)
+    }
+
+    fn all(&mut self, literals: &[Literal], s: StateId) -> Result<bool, String> {
+        for &l in literals {
+            if !self.literal(l, s)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// states reachable from s through a states with a maximal path staying in a
+    /// greatest fixpoint, remove states without successor in the set,
+    /// deadlock states stay, their maximal path ends there
+    /// with a node, states already decided for it are not expanded and every visited one is stored
+    fn globally(
+        &mut self,
+        s: StateId,
+        a: Literal,
+        node: Option<Node>,
+    ) -> Result<HashMap<StateId, bool>, String> {
+        let mut result = HashMap::new();
+        let mut region = HashMap::new();
+        let mut to_explore = vec![s];
+        while let Some(c) = to_explore.pop() {
+            if result.contains_key(&c) || region.contains_key(&c) {
+                continue;
+            }
+            let decided = node.and_then(|n| self.memo.get(&(n.key, c)).map(|&v| v == n.found));
+            if let Some(value) = decided {
+                result.insert(c, value);
+            } else if !self.literal(a, c)? {
+                result.insert(c, false);
+            } else {
+                let successors = self.successors(c);
+                to_explore.extend(successors.iter().map(|e| e.state_id));
+                region.insert(c, successors);
+            }
+        }
+
+        let mut pred: HashMap<StateId, Vec<StateId>> = HashMap::new();
+        for (&c, edges) in &region {
+            for edge in edges {
+                pred.entry(edge.state_id).or_default().push(c);
+            }
+        }
+        let inside = |t: &StateId| region.contains_key(t) || result.get(t) == Some(&true);
+        let mut count = region
+            .iter()
+            .map(|(&c, edges)| (c, edges.iter().filter(|e| inside(&e.state_id)).count()))
+            .collect::<HashMap<_, _>>();
+        let mut removed = region
+            .iter()
+            .filter(|(c, edges)| count[c] == 0 && !edges.is_empty())
+            .map(|(&c, _)| c)
+            .collect::<Vec<_>>();
+        let mut outside = removed.iter().copied().collect::<HashSet<_>>();
+        while let Some(c) = removed.pop() {
+            for &p in pred.get(&c).into_iter().flatten() {
+                if !outside.contains(&p) {
+                    let n = count.get_mut(&p).unwrap();
+                    *n -= 1;
+                    if *n == 0 {
+                        outside.insert(p);
+                        removed.push(p);
+                    }
+                }
+            }
+        }
+
+        for &c in region.keys() {
+            let value = !outside.contains(&c);
+            if let Some(n) = node {
+                self.memo.insert((n.key, c), value == n.found);
+            }
+            result.insert(c, value);
+        }
+        Ok(result)
+    }
+
+    /// witness if the formula holds and is existential
+    /// counterexample if the formula is violated and universal
+    fn explain(
+        &mut self,
+        s: StateId,
+        formula: &Formula,
+        holds: bool,
+    ) -> Result<Option<Trace>, String> {
+        let path = match formula {
+            Formula::Not(a) => return self.explain(s, a, !holds),
+            Formula::ExistsNext(a) if holds => self.next(s, pos(a))?.map(|e| vec![e]),
+            Formula::AllNext(a) if !holds => self.next(s, neg(a))?.map(|e| vec![e]),
+            Formula::ExistsFinally(a) if holds => self.until(s, TRUE, &[pos(a)], None)?,
+            Formula::AllGlobally(a) if !holds => self.until(s, TRUE, &[neg(a)], None)?,
+            Formula::ExistsUntil(a, b) if holds => self.until(s, pos(a), &[pos(b)], None)?,
+            Formula::ExistsGlobally(a) if holds => return self.globally_path(s, pos(a)),
+            Formula::AllFinally(a) if !holds => return self.globally_path(s, neg(a)),
+            Formula::AllUntil(a, b) if !holds => {
+                match self.until(s, neg(b), &[neg(a), neg(b)], None)? {
+                    Some(steps) => Some(steps),
+                    None => return self.globally_path(s, neg(b)),
+                }
+            }
+            _ => None,
+        };
+        Ok(path.map(|steps| Trace {
+            start: s,
+            steps,
+            loop_start: None,
+        }))
+    }
+
+    /// maximal path inside the set, ends in a deadlock or a loop
+    fn globally_path(&mut self, s: StateId, a: Literal) -> Result<Option<Trace>, String> {
+        let set = self.globally(s, a, None)?;
+        if !set[&s] {
+            return Ok(None);
+        }
+        let mut trace = Trace {
+            start: s,
+            steps: vec![],
+            loop_start: None,
+        };
+        let mut visited = HashMap::from([(s, 0)]);
+        let mut cur = s;
+        while let Some(edge) = self
+            .successors(cur)
+            .into_iter()
+            .find(|e| set.get(&e.state_id) == Some(&true))
+        {
+            trace.steps.push(edge);
+            if let Some(&idx) = visited.get(&edge.state_id) {
+                trace.loop_start = Some(idx);
+                break;
+            }
+            visited.insert(edge.state_id, trace.steps.len());
+            cur = edge.state_id;
+        }
+        Ok(Some(trace))
+    }
+}
+
+/// Parse a CTL formula
+/// unary operators bind strongest, then &, |, -> (right associative)
+/// !f | EX f | EF f | EG f | AX f | AF f | AG f | E[f U f] | A[f U f] | (f) | true | false | <atom>
+pub fn parse(input: &str) -> Result<Formula, String> {
+    match implies(input) {
+        Ok(("", formula)) => Ok(formula),
+        Ok((rest, _)) => Err(format!("unexpected input: {}", rest)),
+        Err(e) => Err(format!("invalid formula: {}", e)),
+    }
+}
+
+fn implies(input: &str) -> IResult<&str, Formula> {
+    let (input, a) = or(input)?;
+    match preceded(ws(tag("->")), implies)(input) {
+        Ok((input, b)) => Ok((input, Formula::Implies(Box::new(a), Box::new(b)))),
+        Err(_) => Ok((input, a)),
+    }
+}
+
+fn or(input: &str) -> IResult<&str, Formula> {
+    let (input, first) = and(input)?;
+    let (input, rest) = many0(preceded(ws(char('|')), and))(input)?;
+    let formula = rest
+        .into_iter()
+        .fold(first, |a, b| Formula::Or(Box::new(a), Box::new(b)));
+    Ok((input, formula))
+}
+
+fn and(input: &str) -> IResult<&str, Formula> {
+    let (input, first) = unary(input)?;
+    let (input, rest) = many0(preceded(ws(char('&')), unary))(input)?;
+    let formula = rest
+        .into_iter()
+        .fold(first, |a, b| Formula::And(Box::new(a), Box::new(b)));
+    Ok((input, formula))
+}
+
+fn unary(input: &str) -> IResult<&str, Formula> {
+    let until = |q: &'static str| {
+        tuple((
+            keyword(q),
+            ws(char('[')),
+            implies,
+            keyword("U"),
+            implies,
+            ws(char(']')),
+        ))
+    };
+    alt((
+        map(preceded(ws(char('!')), unary), |f| {
+            Formula::Not(Box::new(f))
+        }),
+        map(preceded(keyword("EX"), unary), |f| {
+            Formula::ExistsNext(Box::new(f))
+        }),
+        map(preceded(keyword("EF"), unary), |f| {
+            Formula::ExistsFinally(Box::new(f))
+        }),
+        map(preceded(keyword("EG"), unary), |f| {
+            Formula::ExistsGlobally(Box::new(f))
+        }),
+        map(preceded(keyword("AX"), unary), |f| {
+            Formula::AllNext(Box::new(f))
+        }),
+        map(preceded(keyword("AF"), unary), |f| {
+            Formula::AllFinally(Box::new(f))
+        }),
+        map(preceded(keyword("AG"), unary), |f| {
+            Formula::AllGlobally(Box::new(f))
+        }),
+        map(until("E"), |(_, _, a, _, c, _)| {
+            Formula::ExistsUntil(Box::new(a), Box::new(c))
+        }),
+        map(until("A"), |(_, _, a, _, c, _)| {
+            Formula::AllUntil(Box::new(a), Box::new(c))
+        }),
+        delimited(ws(char('(')), implies, ws(char(')'))),
+        value(Formula::True, keyword("true")),
+        value(Formula::False, keyword("false")),
+        map(atom, Formula::Atom),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        model_checking::{
+            ltl,
+            proposition::{Atom, CmpOp},
+        },
+        sync_reachability_graph::{
+            build_graph::{build_sync_reachability_graph, build_sync_reachability_graph_with},
+            graph::Graph,
+            options::ExploreOptions,
+            state_space::{expand, StateSpace},
+        },
+        testing::example,
+    };
+
+    use super::{check, parse, Formula};
+
+    #[test]
+    fn test_parse_formula() {
+        let formula = parse("AG (a1 >= 1 -> EF enabled(t1))").unwrap();
+        let expected = Formula::AllGlobally(Box::new(Formula::Implies(
+            Box::new(Formula::Atom(Atom::PlaceCount {
+                place: "a1".to_string(),
+                op: CmpOp::Ge,
+                value: 1,
+            })),
+            Box::new(Formula::ExistsFinally(Box::new(Formula::Atom(
+                Atom::Enabled("t1".to_string()),
+            )))),
+        )));
+        assert_eq!(formula, expected);
+        assert!(parse("E[!deadlock U a2 = 1]").is_ok());
+        assert!(parse("AG").is_err());
+    }
+
+    #[test]
+    fn test_check_choice() {
+        let net = example("ba2022/P005.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        let space = &mut StateSpace::initial(&graph);
+
+        let result = check(&net, &graph, space, &parse("AF deadlock").unwrap()).unwrap();
+        assert!(result.holds);
+
+        let result = check(&net, &graph, space, &parse("EF a1 > 0").unwrap()).unwrap();
+        assert!(result.holds);
+        assert_eq!(result.trace.unwrap().steps.len(), 1);
+
+        let result = check(&net, &graph, space, &parse("AG a2 == 0").unwrap()).unwrap();
+        assert!(!result.holds);
+        let trace = result.trace.unwrap();
+        assert_eq!(
+            net.transition_name(trace.steps[0].module_id, trace.steps[0].transition_id),
+            "t2"
+        );
+
+        assert!(check(&net, &graph, space, &parse("EF x > 0").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_check_local() {
+        let net = example("symmetry/S001.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        let states = expand(&net, &graph).states.len();
+
+        // only the initial state and its successors
+        let space = &mut StateSpace::initial(&graph);
+        assert!(
+            check(&net, &graph, space, &parse("EX m = 0").unwrap())
+                .unwrap()
+                .holds
+        );
+        assert_eq!(space.states.len(), space.edges[0].len() + 1);
+
+        // the search stops at the first violation
+        let space = &mut StateSpace::initial(&graph);
+        let result = check(&net, &graph, space, &parse("AG m = 1").unwrap()).unwrap();
+        assert!(!result.holds);
+        assert!(space.states.len() < states);
+
+        let space = &mut StateSpace::initial(&graph);
+        assert!(
+            check(&net, &graph, space, &parse("AG EF m = 1").unwrap())
+                .unwrap()
+                .holds
+        );
+        assert_eq!(space.states.len(), states);
+    }
+
+    #[test]
+    fn test_check_ltl() {
+        // the path operators agree with LTL on every example
+        for path in [
+            "ba2022/P001.pnawir",
+            "ba2022/P002.pnawir",
+            "ba2022/P003.pnawir",
+            "ba2022/P004.pnawir",
+            "ba2022/P005.pnawir",
+            "ba2022/P006.pnawir",
+            "symmetry/S001.pnawir",
+        ] {
+            let net = example(path);
+            let graph = build_sync_reachability_graph(&net);
+            let full = expand(&net, &graph);
+            let mut atoms = vec!["deadlock".to_string()];
+            for module in &net.modules {
+                for place in &module.places {
+                    atoms.push(format!("{}.{} > 0", module.name, place.name));
+                }
+            }
+            for (p, q) in atoms.iter().zip(atoms.iter().cycle().skip(1)) {
+                for (c, l) in [
+                    (format!("AG {}", p), format!("G {}", p)),
+                    (format!("AF {}", p), format!("F {}", p)),
+                    (format!("A[{} U {}]", p, q), format!("{} U {}", p, q)),
+                    (format!("!EG {}", p), format!("F !{}", p)),
+                    (format!("!EF {}", p), format!("G !{}", p)),
+                    (format!("!E[{} U {}]", p, q), format!("!{} R !{}", p, q)),
+                ] {
+                    let space = &mut StateSpace::initial(&graph);
+                    let ctl = check(&net, &graph, space, &parse(&c).unwrap()).unwrap();
+                    let formula = ltl::parse(&l).unwrap();
+                    let ltl = ltl::check(&net, &graph, &full, &formula).unwrap();
+                    assert_eq!(ctl.holds, ltl.holds, "{}: {}", path, c);
+                    assert_eq!(ctl.trace.is_some(), !ctl.holds, "{}: {}", path, c);
+                    if let Some(trace) = ctl.trace {
+                        let states = trace.states();
+                        for (s, edge) in states.iter().zip(&trace.steps) {
+                            assert!(space.edges[*s].contains(edge));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_symmetry() {
+        // three identical workers sharing a mutex
+        let net = example("symmetry/S001.pnawir");
+        let options = ExploreOptions {
+            symmetry: true,
+            ..Default::default()
+        };
+        let full = build_sync_reachability_graph(&net);
+        let reduced = build_sync_reachability_graph_with(&net, &options);
+        let holds = |graph: &Graph, formula| {
+            let space = &mut StateSpace::initial(graph);
+            check(&net, graph, space, &parse(formula).unwrap()).map(|r| r.holds)
+        };
+
+        // the mutex and deadlocks do not tell the workers apart
+        for formula in ["AG EF m = 1", "EF m = 0", "AG !deadlock", "AG m <= 1"] {
+            assert_eq!(holds(&reduced, formula), holds(&full, formula));
+        }
+
+        // the quotient only keeps one of the permuted states
+        let formula = "EF (i1 = 1 & w2 = 1 & d3 = 1)";
+        assert_eq!(holds(&full, formula), Ok(true));
+        assert_eq!(
+            holds(&reduced, formula),
+            Err(
+                "place i1 distinguishes the symmetric module W1, check it without symmetry"
+                    .to_string()
+            )
+        );
+        assert!(holds(&reduced, "EF enabled(a2)").is_err());
+    }
+}
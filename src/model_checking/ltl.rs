@@ -0,0 +1,511 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, multispace0};
+use nom::combinator::{map, value};
+use nom::multi::many0;
+use nom::sequence::{delimited, preceded, tuple};
+use nom::IResult;
+
+use crate::{
+    modular_net::{ModularPetrinet, ModuleId, TransitionId},
+    parser::parse_input::{name, ws},
+    sync_reachability_graph::{
+        graph::Graph,
+        state_space::{StateEdge, StateId, StateSpace},
+        symmetry::check_preserved,
+    },
+};
+
+use super::{
+    buchi::{self, Nnf},
+    proposition::{atom, keyword, transition_modules, Atom},
+    trace::{CheckResult, Trace},
+};
+
+/// LTL formula
+/// fired(t) holds in a state that was entered by firing t,
+/// a deadlock state is repeated forever without a transition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Formula {
+    True,
+    False,
+    Atom(Atom),
+    Fired(String),
+    Not(Box<Formula>),
+    And(Box<Formula>, Box<Formula>),
+    Or(Box<Formula>, Box<Formula>),
+    Implies(Box<Formula>, Box<Formula>),
+    Next(Box<Formula>),
+    Finally(Box<Formula>),
+    Globally(Box<Formula>),
+    Until(Box<Formula>, Box<Formula>),
+    Release(Box<Formula>, Box<Formula>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Prop {
+    State(Atom),
+    Fired(String),
+}
+
+enum PropValue {
+    State(Vec<bool>),
+    Fired(TransitionKey),
+}
+
+/// extern transitions are identified by id only, intern ones by module and id
+type TransitionKey = (Option<ModuleId>, TransitionId);
+
+/// state of the product: system state, transition it was entered with, automaton node
+type ProductNode = (StateId, Option<TransitionKey>, usize);
+
+/// Check the formula on all maximal runs from the initial state
+/// the trace is a lasso shaped counterexample if the formula is violated,
+/// a counterexample ending in a deadlock has no loop
+pub fn check(
+    net: &ModularPetrinet,
+    graph: &Graph,
+    space: &StateSpace,
+    formula: &Formula,
+) -> Result<CheckResult, String> {
+    // search for a run of the negated formula
+    let mut props = vec![];
+    let nnf = to_nnf(formula, true, &mut props);
+    let values = props
+        .iter()
+        .map(|p| match p {
+            Prop::State(a) => Ok(PropValue::State(a.evaluate(net, graph, space)?)),
+            Prop::Fired(n) => {
+                let (m_id, t_id) = net
+                    .find_transition(n)
+                    .ok_or(format!("unknown transition: {}", n))?;
+                Formula::Fired(n.clone()).preserved(net, &graph.symmetry)?;
+                Ok(PropValue::Fired(transition_key(net, m_id, t_id)))
+            }
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let automaton = buchi::build(&nnf);
+
+    let holds_in = |q: usize, s: StateId, fired: Option<TransitionKey>| {
+        automaton.labels[q]
+            .iter()
+            .all(|&(p, positive)| match &values[p] {
+                PropValue::State(v) => v[s] == positive,
+                PropValue::Fired(key) => (fired == Some(*key)) == positive,
+            })
+    };
+
+    // build the reachable product
+    let mut nodes: Vec<ProductNode> = vec![];
+    let mut edges: Vec<Vec<(usize, Option<StateEdge>)>> = vec![];
+    let mut known = HashMap::new();
+    let mut initial = vec![];
+    for &q in &automaton.initial {
+        if holds_in(q, 0, None) {
+            known.insert((0, None, q), nodes.len());
+            initial.push(nodes.len());
+            nodes.push((0, None, q));
+            edges.push(vec![]);
+        }
+    }
+    let mut to_explore = initial.clone();
+    while let Some(id) = to_explore.pop() {
+        let (s, _, q) = nodes[id];
+        let successors = if space.edges[s].is_empty() {
+            vec![(s, None, None)]
+        } else {
+            space.edges[s]
+                .iter()
+                .map(|e| {
+                    let key = transition_key(net, e.module_id, e.transition_id);
+                    (e.state_id, Some(key), Some(*e))
+                })
+                .collect()
+        };
+        for (next_s, fired, edge) in successors {
+            for &next_q in &automaton.successors[q] {
+                if !holds_in(next_q, next_s, fired) {
+                    continue;
+                }
+                let node = (next_s, fired, next_q);
+                let next_id = match known.get(&node) {
+                    Some(&x) => x,
+                    None => {
+                        let x = nodes.len();
+                        known.insert(node, x);
+                        nodes.push(node);
+                        edges.push(vec![]);
+                        to_explore.push(x);
+                        x
+                    }
+                };
+                edges[id].push((next_id, edge));
+            }
+        }
+    }
+
+    // accepting, nontrivial strongly connected component
+    for scc in strongly_connected_components(&edges) {
+        let members = scc.iter().copied().collect::<HashSet<_>>();
+        let nontrivial = scc.len() > 1 || edges[scc[0]].iter().any(|e| e.0 == scc[0]);
+        let accepting = automaton
+            .accepting
+            .iter()
+            .all(|set| scc.iter().any(|&n| set[nodes[n].2]));
+        if !nontrivial || !accepting {
+            continue;
+        }
+
+        let start = scc[0];
+        let prefix = shortest_path(&edges, &initial, |n| n == start, |_| true, false)
+            .expect("scc is reachable");
+        let mut cycle = vec![];
+        let mut cur = start;
+        for set in &automaton.accepting {
+            let path = shortest_path(
+                &edges,
+                &[cur],
+                |n| set[nodes[n].2],
+                |n| members.contains(&n),
+                false,
+            )
+            .expect("accepting node in scc");
+            cur = path.last().map_or(cur, |e| e.0);
+            cycle.extend(path);
+        }
+        let back = shortest_path(
+            &edges,
+            &[cur],
+            |n| n == start,
+            |n| members.contains(&n),
+            cycle.is_empty(),
+        )
+        .expect("scc is strongly connected");
+        cycle.extend(back);
+
+        let mut trace = Trace {
+            start: 0,
+            steps: prefix.iter().filter_map(|e| e.1).collect(),
+            loop_start: None,
+        };
+        if cycle.iter().all(|e| e.1.is_some()) {
+            trace.loop_start = Some(trace.steps.len());
+            trace.steps.extend(cycle.iter().filter_map(|e| e.1));
+        }
+        return Ok(CheckResult {
+            holds: false,
+            trace: Some(trace),
+        });
+    }
+
+    Ok(CheckResult {
+        holds: true,
+        trace: None,
+    })
+}
+
+fn transition_key(net: &ModularPetrinet, m_id: ModuleId, t_id: TransitionId) -> TransitionKey {
+    if t_id < net.intern_transition_start {
+        (None, t_id)
+    } else {
+        (Some(m_id), t_id)
+    }
+}
+
+impl Formula {
+    /// the value does not change under a permutation within the classes of identical modules
+    pub fn preserved(
+        &self,
+        net: &ModularPetrinet,
+        classes: &[Vec<ModuleId>],
+    ) -> Result<(), String> {
+        match self {
+            Formula::True | Formula::False => Ok(()),
+            Formula::Atom(a) => a.preserved(net, classes),
+            Formula::Fired(t) => {
+                let (_, modules) = transition_modules(net, t)?;
+                check_preserved(net, classes, &modules, &format!("transition {}", t))
+            }
+            Formula::Not(a) | Formula::Next(a) | Formula::Finally(a) | Formula::Globally(a) => {
+                a.preserved(net, classes)
+            }
+            Formula::And(a, b)
+            | Formula::Or(a, b)
+            | Formula::Implies(a, b)
+            | Formula::Until(a, b)
+            | Formula::Release(a, b) => {
+                a.preserved(net, classes)?;
+                b.preserved(net, classes)
+            }
+        }
+    }
+}
+
+/// translate into negation normal form, propositions are collected in props
+fn to_nnf(formula: &Formula, negate: bool, props: &mut Vec<Prop>) -> Nnf {
+    let mut nnf = |f: &Formula, n: bool| Box::new(to_nnf(f, n, props));
+    match (formula, negate) {
+        (Formula::True, false) | (Formula::False, true) => Nnf::True,
+        (Formula::True, true) | (Formula::False, false) => Nnf::False,
+        (Formula::Atom(_) | Formula::Fired(_), _) => {
+            let prop = match formula {
+                Formula::Atom(a) => Prop::State(a.clone()),
+                Formula::Fired(t) => Prop::Fired(t.clone()),
+                _ => unreachable!(),
+            };
+            let idx = match props.iter().position(|p| p == &prop) {
+                Some(x) => x,
+                None => {
+                    props.push(prop);
+                    props.len() - 1
+                }
+            };
+            Nnf::Lit(idx, !negate)
+        }
+        (Formula::Not(a), _) => to_nnf(a, !negate, props),
+        (Formula::And(a, b), false) | (Formula::Or(a, b), true) => {
+            Nnf::And(nnf(a, negate), nnf(b, negate))
+        }
+        (Formula::Or(a, b), false) | (Formula::And(a, b), true) => {
+            Nnf::Or(nnf(a, negate), nnf(b, negate))
+        }
+        (Formula::Implies(a, b), false) => Nnf::Or(nnf(a, true), nnf(b, false)),
+        (Formula::Implies(a, b), true) => Nnf::And(nnf(a, false), nnf(b, true)),
+        (Formula::Next(a), _) => Nnf::Next(nnf(a, negate)),
+        (Formula::Finally(a), false) | (Formula::Globally(a), true) => {
+            Nnf::Until(Box::new(Nnf::True), nnf(a, negate))
+        }
+        (Formula::Globally(a), false) | (Formula::Finally(a), true) => {
+            Nnf::Release(Box::new(Nnf::False), nnf(a, negate))
+        }
+        (Formula::Until(a, b), false) | (Formula::Release(a, b), true) => {
+            Nnf::Until(nnf(a, negate), nnf(b, negate))
+        }
+        (Formula::Release(a, b), false) | (Formula::Until(a, b), true) => {
+            Nnf::Release(nnf(a, negate), nnf(b, negate))
+        }
+    }
+}
+
+/// Tarjan, iterative
+fn strongly_connected_components(edges: &[Vec<(usize, Option<StateEdge>)>]) -> Vec<Vec<usize>> {
+    let n = edges.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut result = vec![];
+    let mut counter = 0;
+
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        index[root] = counter;
+        low[root] = counter;
+        counter += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        let mut call = vec![(root, 0)];
+
+        while let Some(top) = call.last_mut() {
+            let v = top.0;
+            if top.1 < edges[v].len() {
+                let w = edges[v][top.1].0;
+                top.1 += 1;
+                if index[w] == usize::MAX {
+                    index[w] = counter;
+                    low[w] = counter;
+                    counter += 1;
+                    stack.push(w);
+                    on_stack[w] = true;
+                    call.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            call.pop();
+            if let Some(&(u, _)) = call.last() {
+                low[u] = low[u].min(low[v]);
+            }
+            if low[v] == index[v] {
+                let mut scc = vec![];
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    scc.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                result.push(scc);
+            }
+        }
+    }
+    result
+}
+
+/// breadth first search from the start nodes to a target, only through allowed nodes
+fn shortest_path(
+    edges: &[Vec<(usize, Option<StateEdge>)>],
+    start: &[usize],
+    target: impl Fn(usize) -> bool,
+    allowed: impl Fn(usize) -> bool,
+    at_least_one_step: bool,
+) -> Option<Vec<(usize, Option<StateEdge>)>> {
+    if !at_least_one_step && start.iter().any(|&s| target(s)) {
+        return Some(vec![]);
+    }
+    let mut parent: HashMap<usize, (usize, Option<StateEdge>)> = HashMap::new();
+    let mut visited = start.iter().copied().collect::<HashSet<_>>();
+    let mut to_explore = start.iter().copied().collect::<VecDeque<_>>();
+    while let Some(c) = to_explore.pop_front() {
+        for &(next, edge) in &edges[c] {
+            if !allowed(next) {
+                continue;
+            }
+            if target(next) {
+                let mut path = vec![(next, edge)];
+                let mut cur = c;
+                while let Some(&(prev, e)) = parent.get(&cur) {
+                    path.push((cur, e));
+                    cur = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if visited.insert(next) {
+                parent.insert(next, (c, edge));
+                to_explore.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+/// Parse an LTL formula
+/// unary operators bind strongest, then U and R (right associative), &, |, ->
+/// !f | X f | F f | G f | f U f | f R f | (f) | true | false | fired(<transition>) | <atom>
+pub fn parse(input: &str) -> Result<Formula, String> {
+    match implies(input) {
+        Ok(("", formula)) => Ok(formula),
+        Ok((rest, _)) => Err(format!("unexpected input: {}", rest)),
+        Err(e) => Err(format!("invalid formula: {}", e)),
+    }
+}
+
+fn implies(input: &str) -> IResult<&str, Formula> {
+    let (input, a) = or(input)?;
+    match preceded(ws(tag("->")), implies)(input) {
+        Ok((input, b)) => Ok((input, Formula::Implies(Box::new(a), Box::new(b)))),
+        Err(_) => Ok((input, a)),
+    }
+}
+
+fn or(input: &str) -> IResult<&str, Formula> {
+    let (input, first) = and(input)?;
+    let (input, rest) = many0(preceded(ws(char('|')), and))(input)?;
+    let formula = rest
+        .into_iter()
+        .fold(first, |a, b| Formula::Or(Box::new(a), Box::new(b)));
+    Ok((input, formula))
+}
+
+fn and(input: &str) -> IResult<&str, Formula> {
+    let (input, first) = binary(input)?;
+    let (input, rest) = many0(preceded(ws(char('&')), binary))(input)?;
+    let formula = rest
+        .into_iter()
+        .fold(first, |a, b| Formula::And(Box::new(a), Box::new(b)));
+    Ok((input, formula))
+}
+
+fn binary(input: &str) -> IResult<&str, Formula> {
+    let (input, a) = unary(input)?;
+    if let Ok((input, b)) = preceded(keyword("U"), binary)(input) {
+        return Ok((input, Formula::Until(Box::new(a), Box::new(b))));
+    }
+    if let Ok((input, b)) = preceded(keyword("R"), binary)(input) {
+        return Ok((input, Formula::Release(Box::new(a), Box::new(b))));
+    }
+    Ok((input, a))
+}
+
+fn unary(input: &str) -> IResult<&str, Formula> {
+    alt((
+        map(preceded(ws(char('!')), unary), |f| {
+            Formula::Not(Box::new(f))
+        }),
+        map(preceded(keyword("X"), unary), |f| {
+            Formula::Next(Box::new(f))
+        }),
+        map(preceded(keyword("F"), unary), |f| {
+            Formula::Finally(Box::new(f))
+        }),
+        map(preceded(keyword("G"), unary), |f| {
+            Formula::Globally(Box::new(f))
+        }),
+        delimited(ws(char('(')), implies, ws(char(')'))),
+        value(Formula::True, keyword("true")),
+        value(Formula::False, keyword("false")),
+        map(
+            tuple((keyword("fired"), char('('), name, char(')'), multispace0)),
+            |(_, _, n, _, _)| Formula::Fired(n.to_string()),
+        ),
+        map(atom, Formula::Atom),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_reachability_graph::{
+            build_graph::build_sync_reachability_graph, state_space::expand,
+        },
+        testing::example,
+    };
+
+    use super::{check, parse, Formula};
+
+    #[test]
+    fn test_parse_formula() {
+        let formula = parse("G (fired(t1) -> X F deadlock)").unwrap();
+        assert!(matches!(formula, Formula::Globally(_)));
+        let formula = parse("a1 > 0 U fired(t2) | b1 = 1").unwrap();
+        assert!(matches!(formula, Formula::Or(_, _)));
+        assert!(parse("G").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let net = example("ba2022/P005.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        let space = expand(&net, &graph);
+
+        assert!(
+            check(&net, &graph, &space, &parse("F deadlock").unwrap())
+                .unwrap()
+                .holds
+        );
+        let result = check(&net, &graph, &space, &parse("G !fired(t2)").unwrap()).unwrap();
+        assert!(!result.holds);
+        let trace = result.trace.unwrap();
+        assert_eq!(trace.steps.len(), 1);
+        assert_eq!(trace.loop_start, None);
+
+        // a1 and a2 toggle, t2 can be avoided forever
+        let net = example("ba2022/P006.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        let space = expand(&net, &graph);
+        let result = check(&net, &graph, &space, &parse("F fired(t2)").unwrap()).unwrap();
+        assert!(!result.holds);
+        assert!(result.trace.unwrap().loop_start.is_some());
+        assert!(
+            check(&net, &graph, &space, &parse("G !deadlock").unwrap())
+                .unwrap()
+                .holds
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while1};
+use nom::character::complete::{char, multispace0, satisfy};
+use nom::combinator::{map, map_res, not, value};
+use nom::sequence::{delimited, terminated, tuple};
+use nom::IResult;
+
+use crate::{
+    modular_net::{ModularPetrinet, ModuleId, PlaceId, TransitionId},
+    parser::parse_input::{name, place_name, ws},
+    sync_reachability_graph::{
+        graph::Graph,
+        state_space::{StateId, StateSpace},
+        symmetry::check_preserved,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl CmpOp {
+    pub fn apply(&self, a: u32, b: u32) -> bool {
+        match self {
+            CmpOp::Lt => a < b,
+            CmpOp::Le => a <= b,
+            CmpOp::Eq => a == b,
+            CmpOp::Ne => a != b,
+            CmpOp::Ge => a >= b,
+            CmpOp::Gt => a > b,
+        }
+    }
+}
+
+/// Atomic proposition over a global state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Atom {
+    /// <place> <op> <count>
+    PlaceCount {
+        place: String,
+        op: CmpOp,
+        value: u32,
+    },
+    /// enabled(<transition>)
+    Enabled(String),
+    /// no transition is enabled
+    Deadlock,
+}
+
+/// id of a transition and the modules it exists in
+pub(crate) fn transition_modules(
+    net: &ModularPetrinet,
+    name: &str,
+) -> Result<(TransitionId, Vec<ModuleId>), String> {
+    let (m_id, t_id) = net
+        .find_transition(name)
+        .ok_or(format!("unknown transition: {}", name))?;
+    let modules = if t_id < net.intern_transition_start {
+        net.extern_t_overview[t_id as usize].clone()
+    } else {
+        vec![m_id]
+    };
+    Ok((t_id, modules))
+}
+
+impl Atom {
+    /// the value does not change under a permutation within the classes of identical modules
+    pub fn preserved(
+        &self,
+        net: &ModularPetrinet,
+        classes: &[Vec<ModuleId>],
+    ) -> Result<(), String> {
+        match self {
+            Atom::PlaceCount { place, .. } => {
+                let (m_id, _) = net.find_place(place)?;
+                check_preserved(net, classes, &[m_id], &format!("place {}", place))
+            }
+            Atom::Enabled(transition) => {
+                let (_, modules) = transition_modules(net, transition)?;
+                check_preserved(
+                    net,
+                    classes,
+                    &modules,
+                    &format!("transition {}", transition),
+                )
+            }
+            Atom::Deadlock => Ok(()),
+        }
+    }
+
+    /// evaluate the proposition for every state of the state space
+    /// with symmetry it may not depend on the modules of a class
+    pub fn evaluate(
+        &self,
+        net: &ModularPetrinet,
+        graph: &Graph,
+        space: &StateSpace,
+    ) -> Result<Vec<bool>, String> {
+        self.preserved(net, &graph.symmetry)?;
+        let atom = self.resolve(net)?;
+        Ok((0..space.states.len())
+            .map(|s| atom.holds(net, graph, space, s))
+            .collect())
+    }
+
+    /// look up the names in the net
+    pub fn resolve(&self, net: &ModularPetrinet) -> Result<Resolved, String> {
+        Ok(match self {
+            Atom::PlaceCount { place, op, value } => {
+                let (m_id, p_id) = net.find_place(place)?;
+                Resolved::PlaceCount(m_id, p_id, *op, *value)
+            }
+            Atom::Enabled(transition) => {
+                let (t_id, modules) = transition_modules(net, transition)?;
+                Resolved::Enabled(t_id, modules)
+            }
+            Atom::Deadlock => Resolved::Deadlock,
+        })
+    }
+}
+
+/// Atom with the names looked up in the net
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolved {
+    PlaceCount(ModuleId, PlaceId, CmpOp, u32),
+    Enabled(TransitionId, Vec<ModuleId>),
+    Deadlock,
+}
+
+impl Resolved {
+    /// evaluate the proposition in one state, deadlock needs the state to be expanded
+    pub fn holds(
+        &self,
+        net: &ModularPetrinet,
+        graph: &Graph,
+        space: &StateSpace,
+        s: StateId,
+    ) -> bool {
+        match self {
+            Resolved::PlaceCount(m_id, p_id, op, value) => {
+                op.apply(space.marking(graph, s, *m_id).count(*p_id), *value)
+            }
+            Resolved::Enabled(t_id, modules) => modules
+                .iter()
+                .all(|&m| net.modules[m as usize].is_enabled(&space.marking(graph, s, m), *t_id)),
+            Resolved::Deadlock => space.edges[s].is_empty(),
+        }
+    }
+}
+
+/// Parse an atomic proposition
+/// deadlock | enabled(<transition>) | <place> <op> <count>, the place may be Module.place
+pub fn atom(input: &str) -> IResult<&str, Atom> {
+    alt((
+        map(
+            tuple((ws(tag("enabled")), char('('), name, char(')'), multispace0)),
+            |(_, _, n, _, _)| Atom::Enabled(n.to_string()),
+        ),
+        map(tuple((place_name, cmp_op, number)), |(n, op, value)| {
+            Atom::PlaceCount {
+                place: n.to_string(),
+                op,
+                value,
+            }
+        }),
+        value(Atom::Deadlock, ws(tag("deadlock"))),
+    ))(input)
+}
+
+fn cmp_op(input: &str) -> IResult<&str, CmpOp> {
+    delimited(
+        multispace0,
+        alt((
+            value(CmpOp::Le, tag("<=")),
+            value(CmpOp::Ge, tag(">=")),
+            value(CmpOp::Ne, tag("!=")),
+            value(CmpOp::Eq, tag("==")),
+            value(CmpOp::Eq, tag("=")),
+            value(CmpOp::Lt, tag("<")),
+            value(CmpOp::Gt, tag(">")),
+        )),
+        multispace0,
+    )(input)
+}
+
+fn number(input: &str) -> IResult<&str, u32> {
+    delimited(
+        multispace0,
+        map_res(take_while1(|c: char| c.is_ascii_digit()), str::parse),
+        multispace0,
+    )(input)
+}
+
+/// keyword, not followed by a name character
+pub(crate) fn keyword<'a>(k: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    delimited(
+        multispace0,
+        terminated(tag(k), not(satisfy(|c| c.is_alphanumeric() || c == '_'))),
+        multispace0,
+    )
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+use crate::{
+    modular_net::ModularPetrinet,
+    sync_reachability_graph::{
+        graph::Graph,
+        state_space::{StateEdge, StateId, StateSpace},
+    },
+};
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub holds: bool,
+    pub trace: Option<Trace>,
+}
+
+/// Path through the state space, used for witnesses and counterexamples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub start: StateId,
+    pub steps: Vec<StateEdge>,
+    /// lasso: the last state is equal to states()[loop_start] and the path repeats from there
+    pub loop_start: Option<usize>,
+}
+
+impl Trace {
+    /// all visited states, starting with start
+    pub fn states(&self) -> Vec<StateId> {
+        let mut states = vec![self.start];
+        states.extend(self.steps.iter().map(|e| e.state_id));
+        states
+    }
+
+    pub fn print(&self, net: &ModularPetrinet, graph: &Graph, space: &StateSpace) {
+        self.write(net, graph, space, &mut io::stdout().lock())
+            .expect("writing to stdout");
+    }
+
+    pub fn write(
+        &self,
+        net: &ModularPetrinet,
+        graph: &Graph,
+        space: &StateSpace,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
+        let states = self.states();
+        for (i, &s) in states.iter().enumerate() {
+            if self.loop_start == Some(i) {
+                writeln!(out, "  -- loop start --")?;
+            }
+            write!(out, "  State {}: ", s)?;
+            for module in &net.modules {
+                for p in &space.marking(graph, s, module.id).place_counts {
+                    write!(
+                        out,
+                        "{}({}), ",
+                        net.place_name(module.id, p.place_id),
+                        p.count
+                    )?;
+                }
+            }
+            writeln!(out)?;
+            if let Some(edge) = self.steps.get(i) {
+                writeln!(
+                    out,
+                    "    {}",
+                    net.transition_name(edge.module_id, edge.transition_id)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -36,3 +36,94 @@ pub struct Transition {
     pub input_places: Vec<(PlaceId, Weight)>,
     pub output_places: Vec<(PlaceId, Weight)>,
 }
+
+impl ModularPetrinet {
+    /// name of a transition, extern transitions are only named in the modules they exist in
+    pub fn transition_name(&self, m_id: ModuleId, t_id: TransitionId) -> &str {
+        if t_id >= self.intern_transition_start {
+            return &self.modules[m_id as usize].transitions[t_id as usize].name;
+        }
+        self.modules
+            .iter()
+            .map(|m| &m.transitions[t_id as usize].name)
+            .find(|n| !n.is_empty())
+            .map_or("", |n| n)
+    }
+
+    /// search a place by name over all modules, Module.place only in the modules with that name
+    /// a name declared in several of the searched modules is ambiguous
+    pub fn find_place(&self, name: &str) -> Result<(ModuleId, PlaceId), String> {
+        let (module_name, place) = match name.split_once('.') {
+            Some((m, p)) => (Some(m), p),
+            None => (None, name),
+        };
+        let mut found = self
+            .modules
+            .iter()
+            .filter(|m| module_name.is_none_or(|n| n == m.name))
+            .filter_map(|m| {
+                let p = m.places.iter().find(|p| p.name == place)?;
+                Some((m.id, p.id))
+            });
+        match (found.next(), found.next()) {
+            (Some(place), None) => Ok(place),
+            (Some(_), Some(_)) => Err(match module_name {
+                Some(m) => format!(
+                    "place {} is ambiguous, module {} is declared twice",
+                    name, m
+                ),
+                None => format!("place {} is ambiguous, qualify it as Module.{}", name, name),
+            }),
+            (None, _) => Err(format!("unknown place: {}", name)),
+        }
+    }
+
+    /// name of a place, as Module.place if another module has a place with the same name
+    pub fn place_name(&self, m_id: ModuleId, p_id: PlaceId) -> String {
+        let module = &self.modules[m_id as usize];
+        let name = &module.places[p_id as usize].name;
+        let ambiguous = self
+            .modules
+            .iter()
+            .any(|m| m.id != m_id && m.places.iter().any(|p| &p.name == name));
+        if ambiguous {
+            format!("{}.{}", module.name, name)
+        } else {
+            name.clone()
+        }
+    }
+
+    /// search a transition by name over all modules
+    pub fn find_transition(&self, name: &str) -> Option<(ModuleId, TransitionId)> {
+        for module in &self.modules {
+            if let Some(t) = module.transitions.iter().find(|t| t.name == name) {
+                return Some((module.id, t.id));
+            }
+        }
+        None
+    }
+}
+
+impl PetrinetModul {
+    /// checks if the transition exists in this module and has enough tokens in all input places
+    pub fn is_enabled(&self, marking: &Marking, t_id: TransitionId) -> bool {
+        let t = &self.transitions[t_id as usize];
+        if t.input_places.len() + t.output_places.len() == 0 {
+            return false;
+        }
+        t.input_places.iter().all(|p| p.1 <= marking.count(p.0))
+    }
+
+    /// fires the transition without checking if it is enabled
+    pub fn fire(&self, marking: &Marking, t_id: TransitionId) -> Marking {
+        let t = &self.transitions[t_id as usize];
+        let mut marking = marking.clone();
+        for place in t.input_places.iter() {
+            marking.update(place.0, place.1, false);
+        }
+        for place in t.output_places.iter() {
+            marking.update(place.0, place.1, true);
+        }
+        marking
+    }
+}
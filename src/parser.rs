@@ -1,2 +1,3 @@
+pub mod format_input;
 pub mod parse_input;
 pub mod transform_input;
@@ -0,0 +1,363 @@
+use crate::modular_net::{ModularPetrinet, PlaceId, Weight};
+
+use std::fmt;
+
+use super::parse_input::{
+    RawParserExpr, RawParserInput, RawParserLine, RawParserOp, RawParserPlace, RawParserTransition,
+};
+
+/// Canonical .pnawir text of the parsed input, declaration order and comments are kept
+/// parsing the text again gives the same input
+pub fn fmt(input: &RawParserInput) -> String {
+    let mut out = String::new();
+    for (i, include) in input.includes.iter().enumerate() {
+        push_lines(&mut out, trim(&include.comments, i == 0, false), "");
+        out.push_str(&format!("include \"{}\"", include.path));
+        push_comment(&mut out, &include.comment);
+    }
+    let includes = !input.includes.is_empty();
+    let header = trim(&input.comments, !includes, false);
+
+    // the block is left out if all transitions are declared in the modules
+    let block = !input.transitions.is_empty() || !input.closing.is_empty();
+    if block {
+        push_lines(&mut out, header, "");
+        out.push_str("{\n");
+        push_transitions(&mut out, &input.transitions, true);
+        push_lines(&mut out, trim(&input.closing, false, true), "    ");
+        out.push_str("}\n");
+    }
+
+    for (i, module) in input.modules.iter().enumerate() {
+        if i > 0 || block {
+            out.push('\n');
+        } else {
+            // without the block the header is in front of the first module
+            if includes {
+                out.push('\n');
+            }
+            push_lines(&mut out, trim(header, true, false), "");
+        }
+        push_lines(&mut out, trim(&module.comments, true, false), "");
+        let mut body = String::new();
+        // places on one line, broken up by the comments
+        let mut line: Vec<String> = vec![];
+        let flush = |body: &mut String, line: &mut Vec<String>| {
+            if !line.is_empty() {
+                body.push_str(&format!("    {}", line.join(" ")));
+                line.clear();
+            }
+        };
+        for (i, p) in module.places.iter().enumerate() {
+            let comments = trim(&p.comments, i == 0, false);
+            if !comments.is_empty() && !line.is_empty() {
+                flush(&mut body, &mut line);
+                body.push('\n');
+            }
+            push_lines(&mut body, comments, "    ");
+            let separator = if i + 1 < module.places.len() { "," } else { "" };
+            line.push(format!("{}{}", raw_place(&p.name, p, 0), separator));
+            if p.comment.is_some() {
+                flush(&mut body, &mut line);
+                push_comment(&mut body, &p.comment);
+            }
+        }
+        if !line.is_empty() {
+            flush(&mut body, &mut line);
+            body.push('\n');
+        }
+        push_transitions(&mut body, &module.transitions, false);
+        push_lines(&mut body, trim(&module.closing, false, true), "    ");
+        let name = match &module.template {
+            Some(t) => format!(
+                "{}[{}] for {} in {}..{}",
+                module.name, t.variable, t.variable, t.start, t.end
+            ),
+            None => module.name.clone(),
+        };
+        out.push_str(&self::module(&name, &body));
+        push_comment(&mut out, &module.comment);
+    }
+    push_lines(&mut out, trim(&input.end, false, true), "");
+    out
+}
+
+/// Canonical .pnawir text of the net
+/// the interface transitions come first, then the intern transitions module by module,
+/// so the transformed text has the same ids
+pub fn fmt_net(net: &ModularPetrinet) -> String {
+    let mut out = String::from("{\n");
+    let start = net.intern_transition_start;
+    for t_id in 0..start {
+        let (mut inputs, mut outputs) = (vec![], vec![]);
+        for &m_id in &net.extern_t_overview[t_id as usize] {
+            let t = &net.modules[m_id as usize].transitions[t_id as usize];
+            let arc = |&(p_id, w): &(PlaceId, Weight)| place(&net.place_name(m_id, p_id), w, 1);
+            inputs.extend(t.input_places.iter().map(arc));
+            outputs.extend(t.output_places.iter().map(arc));
+        }
+        out.push_str(&transition(net.transition_name(0, t_id), inputs, outputs));
+        out.push('\n');
+    }
+    for module in &net.modules {
+        for t in &module.transitions[start as usize..] {
+            let arc =
+                |&(p_id, w): &(PlaceId, Weight)| place(&net.place_name(module.id, p_id), w, 1);
+            let inputs = t.input_places.iter().map(arc).collect();
+            let outputs = t.output_places.iter().map(arc).collect();
+            out.push_str(&transition(&t.name, inputs, outputs));
+            out.push('\n');
+        }
+    }
+    out.push_str("}\n");
+
+    for module in &net.modules {
+        let marking = &net.markings[module.id as usize];
+        let places = module
+            .places
+            .iter()
+            .map(|p| place(&p.name, marking.count(p.id), 0));
+        let places = places.collect::<Vec<_>>();
+        out.push('\n');
+        out.push_str(&self::module(
+            &module.name,
+            &format!("    {}\n", places.join(", ")),
+        ));
+        out.push('\n');
+    }
+    out
+}
+
+/// place of the parsed input, the weight may be an expression
+fn raw_place(name: &str, p: &RawParserPlace, default: Weight) -> String {
+    match &p.weight_expr {
+        Some(expr) => format!("{}({})", name, expr),
+        None => place(name, p.weight as Weight, default),
+    }
+}
+
+/// the weight is left out if it is the default
+fn place(name: &str, weight: Weight, default: Weight) -> String {
+    if weight == default {
+        name.to_string()
+    } else {
+        format!("{}({})", name, weight)
+    }
+}
+
+/// transition lines with their comments, blank lines at the start of a block are left out
+fn push_transitions(out: &mut String, transitions: &[RawParserTransition], block: bool) {
+    let arcs = |places: &[RawParserPlace]| {
+        let arcs = places.iter().map(|p| {
+            let name = match &p.module {
+                Some(module) => format!("{}.{}", module, p.name),
+                None => p.name.clone(),
+            };
+            raw_place(&name, p, 1)
+        });
+        arcs.collect::<Vec<_>>()
+    };
+    for (i, t) in transitions.iter().enumerate() {
+        push_lines(out, trim(&t.comments, block && i == 0, false), "    ");
+        let mut name = t.name.clone();
+        if let Some(index) = &t.index {
+            name = format!("{}[{}]", name, index);
+        }
+        if t.shared {
+            name = format!("shared {}", name);
+        }
+        out.push_str(&transition(
+            &name,
+            arcs(&t.input_places),
+            arcs(&t.output_places),
+        ));
+        push_comment(out, &t.comment);
+    }
+}
+
+fn transition(name: &str, inputs: Vec<String>, outputs: Vec<String>) -> String {
+    let side = |arcs: Vec<String>| {
+        if arcs.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", arcs.join(", "))
+        }
+    };
+    format!("    {}:{} ->{}", name, side(inputs), side(outputs))
+}
+
+/// the body with its line breaks, without the line break after the closing brace
+fn module(name: &str, body: &str) -> String {
+    format!("{} {{\n{}}}", name, body)
+}
+
+/// the lines without the blank lines at the start of a block or at its end
+fn trim(lines: &[RawParserLine], start: bool, end: bool) -> &[RawParserLine] {
+    let mut lines = lines;
+    while start && lines.first() == Some(&RawParserLine::Blank) {
+        lines = &lines[1..];
+    }
+    while end && lines.last() == Some(&RawParserLine::Blank) {
+        lines = &lines[..lines.len() - 1];
+    }
+    lines
+}
+
+fn push_lines(out: &mut String, lines: &[RawParserLine], indent: &str) {
+    for line in lines {
+        match line {
+            RawParserLine::Comment(text) => out.push_str(&format!("{}#{}\n", indent, text)),
+            RawParserLine::Blank => out.push('\n'),
+        }
+    }
+}
+
+/// comment at the end of the line and the line break
+fn push_comment(out: &mut String, comment: &Option<String>) {
+    if let Some(text) = comment {
+        out.push_str(&format!(" #{}", text));
+    }
+    out.push('\n');
+}
+
+impl fmt::Display for RawParserOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            RawParserOp::Add => "+",
+            RawParserOp::Sub => "-",
+            RawParserOp::Mul => "*",
+            RawParserOp::Div => "/",
+            RawParserOp::Rem => "%",
+            RawParserOp::Eq => "==",
+            RawParserOp::Ne => "!=",
+            RawParserOp::Lt => "<",
+            RawParserOp::Le => "<=",
+            RawParserOp::Gt => ">",
+            RawParserOp::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// comparisons bind weakest, then sums, then products
+fn precedence(op: RawParserOp) -> u8 {
+    match op {
+        RawParserOp::Add | RawParserOp::Sub => 1,
+        RawParserOp::Mul | RawParserOp::Div | RawParserOp::Rem => 2,
+        _ => 0,
+    }
+}
+
+/// with the parentheses which are needed to parse it again
+impl fmt::Display for RawParserExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawParserExpr::Number(n) => write!(f, "{}", n),
+            RawParserExpr::Variable(v) => write!(f, "{}", v),
+            RawParserExpr::Binary(left, op, right) => {
+                // the operators are left associative, only one comparison is allowed
+                let side = |e: &RawParserExpr, right: bool| match e {
+                    RawParserExpr::Binary(_, inner, _)
+                        if precedence(*inner) < precedence(*op)
+                            || (precedence(*inner) == precedence(*op)
+                                && (right || precedence(*op) == 0)) =>
+                    {
+                        format!("({})", e)
+                    }
+                    _ => e.to_string(),
+                };
+                write!(f, "{} {} {}", side(left, false), op, side(right, true))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{parse_input::parse, transform_input::transform},
+        testing::{net, read},
+    };
+
+    use super::{fmt, fmt_net};
+
+    #[test]
+    fn test_fmt() {
+        let inputs = [
+            "ba2022/P001.pnawir",
+            "ba2022/P002.pnawir",
+            "ba2022/P003.pnawir",
+            "ba2022/P005.pnawir",
+            "ba2022/P006a.pnawir",
+            "symmetry/S001.pnawir",
+        ];
+        for path in inputs {
+            let input = read(path);
+            let text = fmt(&parse(&input).unwrap().1);
+            // the examples are formatted already
+            assert_eq!(text, input);
+            let again = parse(&text).unwrap();
+            assert!(again.0.trim().is_empty());
+            assert_eq!(fmt(&again.1), text);
+
+            // the net survives the round trip
+            let net_text = fmt_net(&transform(again.1));
+            let net = net(&net_text);
+            assert_eq!(fmt_net(&net), net_text);
+        }
+
+        let input = "# net\n#  with comments\n{\n  t: p1 , p2(2)->p2\n}\nM1{p1(1),p2}\n";
+        let text = fmt(&parse(input).unwrap().1);
+        assert_eq!(
+            text,
+            "# net\n#  with comments\n{\n    t: p1, p2(2) -> p2\n}\n\nM1 {\n    p1(1), p2\n}\n"
+        );
+
+        // comments and blank lines everywhere
+        let input =
+            "\n# net\n{ # block\n  t1: p1 -> p2 # end\n\n\n  # u\n  u: p2 ->\n  # last\n\n}\n\
+            # M1\nM1 {\n  p1(1), # first\n  # second\n  p2\n} # after\n# end \n";
+        let text = fmt(&parse(input).unwrap().1);
+        assert_eq!(
+            text,
+            "# net\n{\n    # block\n    t1: p1 -> p2 # end\n\n    # u\n    u: p2 ->\n    # last\n}\n\
+            \n# M1\nM1 {\n    p1(1), # first\n    # second\n    p2\n} # after\n# end\n"
+        );
+        assert_eq!(fmt(&parse(&text).unwrap().1), text);
+
+        // places with the same name are qualified
+        let input = "{\n    t: M1.p -> M2.p(2)\n    u: M2.p -> q\n}\n\nM1 {\n    p(1), q\n}\n\nM2 {\n    p\n}\n";
+        assert_eq!(fmt(&parse(input).unwrap().1), input);
+        assert_eq!(fmt_net(&net(input)), input);
+
+        // includes
+        let input = "# net\ninclude \"a.pnawir\" # first\n\n# more\ninclude \"b/c.pnawir\"\n\nM1 {\n    p\n}\n";
+        assert_eq!(fmt(&parse(input).unwrap().1), input);
+
+        // templates
+        let input = concat!(
+            "Fork[i] for i in 0..3 {\n    free(i == 0)\n    shared take[i]: free ->\n",
+            "    shared take[(i + 2) % 3]: free ->\n    put[i * (2 - 1)]: -> free(1 + 2 * i)\n}\n"
+        );
+        assert_eq!(fmt(&parse(input).unwrap().1), input);
+        let text = fmt(
+            &parse("F[i] for i in 0 .. 2 {p((i)), q(i+1-(1+i)), r((i<1)+1)}")
+                .unwrap()
+                .1,
+        );
+        assert_eq!(
+            text,
+            "F[i] for i in 0..2 {\n    p(i), q(i + 1 - (1 + i)), r((i < 1) + 1)\n}\n"
+        );
+
+        // transitions declared in the modules
+        let input = "M1 {\n    p(1), q\n    a: p -> q\n    shared t: q -> p\n}\n\n\
+            M2 {\n    p\n    # the other side\n    shared t: -> p(2)\n}\n";
+        assert_eq!(fmt(&parse(input).unwrap().1), input);
+        assert_eq!(
+            fmt_net(&net(input)),
+            "{\n    t: q -> M1.p, M2.p(2)\n    a: M1.p -> q\n}\n\n\
+            M1 {\n    p(1), q\n}\n\nM2 {\n    p\n}\n"
+        );
+    }
+}
@@ -1,183 +1,499 @@
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_while, take_while1};
-use nom::character::complete::{alpha1, line_ending, multispace0, not_line_ending};
-use nom::character::streaming::char;
-use nom::combinator::{peek, value};
-use nom::error::ParseError;
-use nom::multi::{many0, many0_count, many1, many_m_n, separated_list0, separated_list1};
-use nom::sequence::{delimited, tuple};
+use nom::bytes::complete::{tag, take_till, take_while, take_while1};
+use nom::character::complete::{
+    alpha1, char, line_ending, multispace0, not_line_ending, space0, space1,
+};
+use nom::combinator::{eof, map, map_opt, opt, peek, recognize, value};
+use nom::error::{Error, ErrorKind, ParseError};
+use nom::multi::{fold_many0, separated_list0};
+use nom::sequence::{delimited, pair, preceded, separated_pair, terminated, tuple};
 use nom::IResult;
 
 type Weight = u8;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawParserInput {
+    /// other files with modules of the net
+    pub includes: Vec<RawParserInclude>,
+    /// lines before the transitions
+    pub comments: Vec<RawParserLine>,
+    /// transitions of the optional block at the top, the modules follow from the places
     pub transitions: Vec<RawParserTransition>,
+    /// lines before the closing brace of the transitions
+    pub closing: Vec<RawParserLine>,
     pub modules: Vec<RawParserModule>,
+    /// lines after the last module
+    pub end: Vec<RawParserLine>,
 }
 
-#[derive(Debug)]
+/// include "<path>", the path is relative to the including file
+#[derive(Debug, Clone)]
+pub struct RawParserInclude {
+    pub path: String,
+    /// lines before the include
+    pub comments: Vec<RawParserLine>,
+    /// comment at the end of the line
+    pub comment: Option<String>,
+}
+
+/// A comment line, without the #, or a blank line
+/// consecutive blank lines are kept as one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawParserLine {
+    Comment(String),
+    Blank,
+}
+
+#[derive(Debug, Clone)]
 pub struct RawParserTransition {
     pub name: String,
+    /// name[<index>], the value of the index is appended to the name
+    pub index: Option<RawParserExpr>,
+    /// interface transition declared in a module, synchronized with the other modules declaring it
+    pub shared: bool,
     pub input_places: Vec<RawParserPlace>,
     pub output_places: Vec<RawParserPlace>,
+    /// lines before the transition
+    pub comments: Vec<RawParserLine>,
+    /// comment at the end of the line
+    pub comment: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RawParserModule {
     pub name: String,
+    /// the module is instantiated for every value of the index
+    pub template: Option<RawParserTemplate>,
     pub places: Vec<RawParserPlace>,
+    /// transitions declared in the module, only with places of the module
+    pub transitions: Vec<RawParserTransition>,
+    /// lines before the module
+    pub comments: Vec<RawParserLine>,
+    /// lines before the closing brace
+    pub closing: Vec<RawParserLine>,
+    /// comment after the closing brace
+    pub comment: Option<String>,
 }
 
-#[derive(Debug)]
+/// Name[<variable>] for <variable> in <start>..<end>, the end is exclusive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawParserTemplate {
+    pub variable: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+/// Integer expression over the index of a template, a comparison is 1 or 0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RawParserExpr {
+    Number(u32),
+    Variable(String),
+    Binary(Box<RawParserExpr>, RawParserOp, Box<RawParserExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawParserOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// The comments are only used for the places of a module
+#[derive(Debug, Clone)]
 pub struct RawParserPlace {
     pub name: String,
+    /// module of a qualified reference Module.place in a transition
+    pub module: Option<String>,
     pub weight: Weight,
+    /// weight depending on the index of a template, the weight is set on instantiation
+    pub weight_expr: Option<RawParserExpr>,
+    /// lines before the place
+    pub comments: Vec<RawParserLine>,
+    /// comment at the end of the line
+    pub comment: Option<String>,
 }
 
 pub fn parse(input: &str) -> IResult<&str, RawParserInput> {
-    let (input, _) = many0_count(comment)(input)?;
-    let (input, transitions) = transitions(input)?;
-    let (input, modules) = many1(module)(input)?;
+    let (mut input, mut comments) = lines(input)?;
+    let mut includes = vec![];
+    while let Ok((i, path)) = include(input) {
+        let (i, (comment, lines)) = gap(i)?;
+        includes.push(RawParserInclude {
+            path: path.to_string(),
+            comments: std::mem::replace(&mut comments, lines),
+            comment,
+        });
+        input = i;
+    }
+    // the block is optional, the transitions may be declared in the modules
+    let opening = peek::<_, _, Error<&str>, _>(preceded(space0, char('{')))(input);
+    let (input, (transitions, closing)) = match opening {
+        Ok(_) => preceded(space0, transitions)(input)?,
+        Err(_) => (input, Default::default()),
+    };
+    let (mut input, mut next) = gap_before(input)?;
+
+    let mut modules = vec![];
+    // a file may only have includes, the modules are in the other files
+    while !input.trim().is_empty() {
+        let (i, (mut module, comment, lines)) = module(input)?;
+        module.comments = std::mem::replace(&mut next, lines);
+        module.comment = comment;
+        modules.push(module);
+        input = i;
+    }
 
     let raw_parser_input = RawParserInput {
+        includes,
+        comments,
         transitions,
+        closing,
         modules,
+        end: next,
     };
 
     Ok((input, raw_parser_input))
 }
 
+/// Parse an include
+/// include "<path>"
+fn include(input: &str) -> IResult<&str, &str> {
+    let path = delimited(char('"'), take_till(|c| c == '"' || c == '\n'), char('"'));
+    preceded(tuple((space0, tag("include"), space1)), path)(input)
+}
+
 /// Parse Petrinet
 /// { <transitionline> \n ... }
-fn transitions(input: &str) -> IResult<&str, Vec<RawParserTransition>> {
-    let opening = char('{');
-    let closing = char('}');
-    let transitionlines = many1(transitionline);
-
-    let (input, (_, _, transitions, _, _)) = tuple((
-        opening,
-        line_ending,
-        transitionlines,
-        many0(line_ending),
-        closing,
-    ))(input)?;
+fn transitions(input: &str) -> IResult<&str, (Vec<RawParserTransition>, Vec<RawParserLine>)> {
+    let (mut input, mut next) = preceded(char('{'), gap_before)(input)?;
 
-    Ok((input, transitions))
+    let mut transitions = vec![];
+    loop {
+        if !transitions.is_empty() {
+            if let Ok((i, _)) = closing(input) {
+                return Ok((i, (transitions, next)));
+            }
+        }
+        // errors point at the start of the line
+        let (i, mut transition) = preceded(space0, transitionline)(input)
+            .map_err(|e| e.map(|e: Error<&str>| Error { input, ..e }))?;
+        let (i, (comment, lines)) = gap(i)?;
+        transition.comments = std::mem::replace(&mut next, lines);
+        transition.comment = comment;
+        transitions.push(transition);
+        input = i;
+    }
 }
 
-/// Parse Module
-/// <Name> { <place1>, <place2>(<count>), ... }
-fn module(input: &str) -> IResult<&str, RawParserModule> {
-    let opening = |i| char('{')(i);
-    let closing = |i| ws(tag("}"))(i);
-
-    let places_parser = |i| {
-        separated_list1(
-            alt((tag(","), tag("\n"), tag("\r\n"))),
-            place_with_optional_number,
-        )(i)
-    };
-
-    let (input, (_, name, _, places, _, _)) = tuple((
-        many0(line_ending),
-        ws(name),
-        opening,
-        places_parser,
-        many0(line_ending),
-        closing,
+/// Parse the template of a module
+/// [<variable>] for <variable> in <start>..<end>
+fn template(input: &str) -> IResult<&str, RawParserTemplate> {
+    let range = separated_pair(number, tuple((space0, tag(".."), space0)), number);
+    let (rest, (_, variable, _, _, again, _, (start, end))) = tuple((
+        char('['),
+        identifier,
+        pair(char(']'), space1),
+        pair(tag("for"), space1),
+        identifier,
+        tuple((space1, tag("in"), space1)),
+        range,
     ))(input)?;
-
-    let raw_parser_module = RawParserModule {
-        name: name.to_string(),
-        places: set_default_value_for_vec(places, 0),
+    if variable != again {
+        return Err(nom::Err::Error(Error::new(input, ErrorKind::Verify)));
+    }
+    let raw_parser_template = RawParserTemplate {
+        variable: variable.to_string(),
+        start,
+        end,
     };
+    Ok((rest, raw_parser_template))
+}
+
+/// Parse Module
+/// <Name>[<template>] { <place1>, <place2>(<count>), ... [shared] <transitionline> ... }
+/// returns the comment after the closing brace and the lines below as well
+#[allow(clippy::type_complexity)]
+fn module(input: &str) -> IResult<&str, (RawParserModule, Option<String>, Vec<RawParserLine>)> {
+    let (input, (_, name, template, _, _)) =
+        tuple((space0, identifier, opt(template), space0, char('{')))(input)?;
+    let (mut input, mut next) = gap_before(input)?;
 
-    Ok((input, raw_parser_module))
+    let shared = |i| opt(pair(tag("shared"), space1))(i);
+    let mut places = vec![];
+    let mut transitions = vec![];
+    loop {
+        if !places.is_empty() {
+            if let Ok((i, _)) = closing(input) {
+                let (i, (comment, lines)) = gap(i)?;
+                let raw_parser_module = RawParserModule {
+                    name: name.to_string(),
+                    template,
+                    places,
+                    transitions,
+                    comments: vec![],
+                    closing: next,
+                    comment: None,
+                };
+                return Ok((i, (raw_parser_module, comment, lines)));
+            }
+        }
+        let start = tuple((space0, shared, identifier, opt(index), space0, char(':')));
+        if peek::<_, _, Error<&str>, _>(start)(input).is_ok() {
+            // errors point at the start of the line
+            let (i, (_, shared, mut transition)) =
+                tuple((space0, shared, transitionline))(input)
+                    .map_err(|e| e.map(|e: Error<&str>| Error { input, ..e }))?;
+            let (i, (comment, lines)) = gap(i)?;
+            transition.shared = shared.is_some();
+            transition.comments = std::mem::replace(&mut next, lines);
+            transition.comment = comment;
+            transitions.push(transition);
+            input = i;
+            continue;
+        }
+        let (i, place) = preceded(space0, place_with_optional_number)(input)?;
+        let (i, _) = pair(space0, opt(char(',')))(i)?;
+        let (i, (comment, lines)) = gap(i)?;
+        let mut place = set_default_value(place, 0);
+        place.comments = std::mem::replace(&mut next, lines);
+        place.comment = comment;
+        places.push(place);
+        input = i;
+    }
 }
 
 /// Parse a transitionline: transitions with arcs, pre- and postplaces
-/// <transition>: <place1>, <place2>(<count>), ... -> <place1>, ...
+/// <transition>[<index>]: <place1>, <place2>(<count>), ... -> <place1>, ...
 fn transitionline<'a, E: ParseError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, RawParserTransition, E> {
-    let name_parser = name;
-    let colon = char(':');
-    let places_parser = |i| separated_list0(char(','), place_with_optional_number)(i);
-    let arrow = ws(tag("->"));
+    let colon = preceded(space0, char(':'));
+    let places_parser = |i| {
+        separated_list0(
+            tuple((space0, char(','), multispace0)),
+            preceded(space0, arc),
+        )(i)
+    };
+    let arrow = tuple((multispace0, tag("->"), space0));
 
-    let (input, (transition_name, _, input_places, _, output_places)) =
-        tuple((name_parser, colon, places_parser, arrow, places_parser))(input)?;
+    let (input, (transition_name, index, _, input_places, _, output_places)) = tuple((
+        identifier,
+        opt(index),
+        colon,
+        places_parser,
+        arrow,
+        places_parser,
+    ))(input)?;
 
     let proto_transition = RawParserTransition {
         name: transition_name.to_string(),
-        input_places: set_default_value_for_vec(input_places, 1),
-        output_places: set_default_value_for_vec(output_places, 1),
+        index,
+        shared: false,
+        input_places,
+        output_places,
+        comments: vec![],
+        comment: None,
     };
 
     Ok((input, proto_transition))
 }
 
+/// Parse an arc of a transition
+/// <place>(<count>) or <Module>.<place>(<count>), the weight is 1 by default
+fn arc<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserPlace, E> {
+    let (input, module) = opt(terminated(identifier, char('.')))(input)?;
+    let (input, place) = place_with_optional_number(input)?;
+    let raw_parser_place = RawParserPlace {
+        module: module.map(str::to_string),
+        ..set_default_value(place, 1)
+    };
+    Ok((input, raw_parser_place))
+}
+
 /// Parse a place with optional Number
+/// the number may be an expression in a template
 fn place_with_optional_number<'a, E: ParseError<&'a str>>(
     input: &'a str,
-) -> IResult<&'a str, (String, Option<Weight>), E> {
-    let (input, name) = name(input)?;
-    let weight_parser = tuple((char('('), take_while1(char::is_numeric), char(')')));
-    let (input, number) = many_m_n(0, 1, weight_parser)(input)?;
-
-    let weight: Option<Weight>;
-    if number.len() > 0 {
-        weight = Some(number[0].1.parse().unwrap());
-    } else {
-        weight = None;
-    }
+) -> IResult<&'a str, (String, Option<RawParserExpr>), E> {
+    let (input, name) = identifier(input)?;
+    let weight_parser = delimited(pair(char('('), space0), expr, pair(space0, char(')')));
+    let (input, weight) = opt(weight_parser)(input)?;
+
     let raw_parser_place = (name.to_string(), weight);
     Ok((input, raw_parser_place))
 }
 
+/// Parse an index
+/// [<expression>]
+fn index<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserExpr, E> {
+    delimited(pair(char('['), space0), expr, pair(space0, char(']')))(input)
+}
+
+/// Parse an expression
+/// <sum> or <sum> <comparison> <sum>
+fn expr<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserExpr, E> {
+    let comparison = alt((
+        value(RawParserOp::Eq, tag("==")),
+        value(RawParserOp::Ne, tag("!=")),
+        value(RawParserOp::Le, tag("<=")),
+        value(RawParserOp::Ge, tag(">=")),
+        value(RawParserOp::Lt, tag("<")),
+        value(RawParserOp::Gt, tag(">")),
+    ));
+    let (input, left) = sum(input)?;
+    let (input, right) = opt(pair(delimited(space0, comparison, space0), sum))(input)?;
+    let expr = match right {
+        Some((op, right)) => RawParserExpr::Binary(Box::new(left), op, Box::new(right)),
+        None => left,
+    };
+    Ok((input, expr))
+}
+
+/// <product> + <product> - ...
+fn sum<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserExpr, E> {
+    let op = alt((
+        value(RawParserOp::Add, char('+')),
+        value(RawParserOp::Sub, char('-')),
+    ));
+    let (input, first) = product(input)?;
+    fold_many0(
+        pair(delimited(space0, op, space0), product),
+        move || first.clone(),
+        |left, (op, right)| RawParserExpr::Binary(Box::new(left), op, Box::new(right)),
+    )(input)
+}
+
+/// <atom> * <atom> / <atom> % ...
+fn product<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserExpr, E> {
+    let op = alt((
+        value(RawParserOp::Mul, char('*')),
+        value(RawParserOp::Div, char('/')),
+        value(RawParserOp::Rem, char('%')),
+    ));
+    let (input, first) = atom(input)?;
+    fold_many0(
+        pair(delimited(space0, op, space0), atom),
+        move || first.clone(),
+        |left, (op, right)| RawParserExpr::Binary(Box::new(left), op, Box::new(right)),
+    )(input)
+}
+
+/// <number>, <variable> or (<expression>)
+fn atom<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, RawParserExpr, E> {
+    alt((
+        map(number, RawParserExpr::Number),
+        map(identifier, |v: &str| RawParserExpr::Variable(v.to_string())),
+        delimited(pair(char('('), space0), expr, pair(space0, char(')'))),
+    ))(input)
+}
+
+fn number<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, u32, E> {
+    map_opt(take_while1(|c: char| c.is_ascii_digit()), |n: &str| {
+        n.parse().ok()
+    })(input)
+}
+
 /// Parse a name
 /// with isalphanumerical or underscore
-fn name<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, &str, E> {
-    let (i, (_, _, name, _)) = tuple((
-        multispace0,
-        peek(alpha1),
-        take_while(|i: char| i.is_alphanumeric() || i == '_'),
-        multispace0,
-    ))(i)?;
-    Ok((i, name))
+pub(crate) fn name<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, &'a str, E> {
+    delimited(multispace0, identifier, multispace0)(i)
+}
+
+/// Parse a place name, optionally qualified by its module
+/// <place> or <Module>.<place>
+pub(crate) fn place_name<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, &'a str, E> {
+    let qualified = recognize(pair(opt(pair(identifier, char('.'))), identifier));
+    delimited(multispace0, qualified, multispace0)(i)
+}
+
+/// A name without the whitespaces around, starts with a letter
+fn identifier<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, &'a str, E> {
+    let (i, _) = peek(alpha1)(i)?;
+    take_while(|i: char| i.is_alphanumeric() || i == '_')(i)
 }
 
 /// Parse a comment
-/// #This is a comment until a linebreak
-fn comment<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, (), E> {
-    value((), tuple((char('#'), not_line_ending, line_ending)))(i)
+/// #This is a comment until a linebreak, the text without the # and trailing whitespaces
+fn comment<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, String, E> {
+    let (i, text) = preceded(char('#'), not_line_ending)(i)?;
+    Ok((i, text.trim_end().to_string()))
+}
+
+/// Comment lines and blank lines, the rest of the line before is parsed already
+fn lines<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Vec<RawParserLine>, E> {
+    let line = alt((
+        map(
+            delimited(space0, comment, alt((line_ending, eof))),
+            RawParserLine::Comment,
+        ),
+        value(RawParserLine::Blank, pair(space0, line_ending)),
+    ));
+    fold_many0(line, Vec::new, |mut lines, line| {
+        if line != RawParserLine::Blank || lines.last() != Some(&RawParserLine::Blank) {
+            lines.push(line);
+        }
+        lines
+    })(i)
+}
+
+/// Whitespaces and comments after a token
+/// a comment on the same line belongs to the token, the lines below to the next one
+fn gap<'a, E: ParseError<&'a str>>(
+    i: &'a str,
+) -> IResult<&'a str, (Option<String>, Vec<RawParserLine>), E> {
+    let (i, (_, comment, newline)) = tuple((space0, opt(comment), opt(line_ending)))(i)?;
+    match newline {
+        Some(_) => {
+            let (i, lines) = lines(i)?;
+            Ok((i, (comment, lines)))
+        }
+        None => Ok((i, (comment, vec![]))),
+    }
+}
+
+/// Gap after an opening brace, the comment on the same line belongs to the next token
+fn gap_before<'a, E: ParseError<&'a str>>(i: &'a str) -> IResult<&'a str, Vec<RawParserLine>, E> {
+    let (i, (comment, lines)) = gap(i)?;
+    let comment = comment.map(RawParserLine::Comment);
+    Ok((i, comment.into_iter().chain(lines).collect()))
+}
+
+/// Closing brace of the transitions or of a module
+fn closing(i: &str) -> IResult<&str, char> {
+    preceded(space0, char('}'))(i)
 }
 
 /// Trim, ignore whitespaces before and after
-fn ws<'a, F, O, E: ParseError<&'a str>>(inner: F) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
+pub(crate) fn ws<'a, F, O, E: ParseError<&'a str>>(
+    inner: F,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O, E>
 where
     F: Fn(&'a str) -> IResult<&'a str, O, E>,
 {
     delimited(multispace0, inner, multispace0)
 }
 
-fn set_default_value_for_vec(
-    input: Vec<(String, Option<Weight>)>,
-    value: Weight,
-) -> Vec<RawParserPlace> {
-    input
-        .into_iter()
-        .map(|p| set_default_value(p, value))
-        .collect::<Vec<RawParserPlace>>()
-}
-
-fn set_default_value(input: (String, Option<Weight>), value: Weight) -> RawParserPlace {
+fn set_default_value(input: (String, Option<RawParserExpr>), value: Weight) -> RawParserPlace {
+    // anything but a small number is evaluated on instantiation
+    let (weight, weight_expr) = match input.1 {
+        None => (value, None),
+        Some(RawParserExpr::Number(n)) if n <= Weight::MAX as u32 => (n as Weight, None),
+        Some(expr) => (value, Some(expr)),
+    };
     RawParserPlace {
         name: input.0.to_string(),
-        weight: match input.1 {
-            Some(x) => x,
-            None => value,
-        },
+        module: None,
+        weight,
+        weight_expr,
+        comments: vec![],
+        comment: None,
     }
 }
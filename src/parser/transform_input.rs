@@ -5,22 +5,308 @@ use crate::{
     sync_reachability_graph::graph::{Marking, PlaceCount},
 };
 
-use super::parse_input::{RawParserInput, RawParserModule, RawParserPlace, RawParserTransition};
+use super::parse_input::{
+    RawParserExpr, RawParserInput, RawParserModule, RawParserOp, RawParserPlace,
+    RawParserTransition,
+};
 
-pub fn transform(input: RawParserInput) -> ModularPetrinet {
-    // build place -> module Map
-    let mut place_to_module = HashMap::new();
+/// Modules declaring each place name
+struct Names<'a> {
+    places: HashMap<&'a str, Vec<usize>>,
+    modules: Vec<&'a str>,
+}
+
+impl<'a> Names<'a> {
+    fn new(input: &'a RawParserInput) -> Self {
+        let mut places: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, module) in input.modules.iter().enumerate() {
+            for place in &module.places {
+                places.entry(place.name.as_str()).or_default().push(i);
+            }
+        }
+        let modules = input.modules.iter().map(|m| m.name.as_str()).collect();
+        Names { places, modules }
+    }
+
+    /// module of a place referenced by a transition
+    /// a local name has to be unique, otherwise it is qualified as Module.place
+    fn resolve(&self, place: &RawParserPlace) -> Result<usize, String> {
+        let declared = self.places.get(place.name.as_str());
+        let mut modules = declared.into_iter().flatten().copied();
+        match &place.module {
+            Some(name) => {
+                let mut modules = modules.filter(|&m| self.modules[m] == name);
+                match (modules.next(), modules.next()) {
+                    (Some(module), None) => Ok(module),
+                    (Some(_), Some(_)) => Err(format!(
+                        "place {}.{} is ambiguous, module {} is declared twice",
+                        name, place.name, name
+                    )),
+                    (None, _) if !self.modules.contains(&name.as_str()) => {
+                        Err(format!("module {} does not exist", name))
+                    }
+                    (None, _) => Err(format!("module {} has no place {}", name, place.name)),
+                }
+            }
+            None => match (modules.next(), modules.next()) {
+                (Some(module), None) => Ok(module),
+                (Some(_), Some(_)) => Err(format!(
+                    "place {} is declared in several modules, qualify it as Module.{}",
+                    place.name, place.name
+                )),
+                (None, _) => Err(format!("place {} belongs to no module", place.name)),
+            },
+        }
+    }
+
+    /// a place referenced by a transition declared in the module has to be in the module
+    fn resolve_in(&self, place: &RawParserPlace, module: usize) -> Result<usize, String> {
+        let name = self.modules[module];
+        let declared = self.places.get(place.name.as_str());
+        let own = place.module.as_ref().is_none_or(|m| m == name);
+        if own && declared.is_some_and(|modules| modules.contains(&module)) {
+            return Ok(module);
+        }
+        let qualified = match &place.module {
+            Some(m) => format!("{}.{}", m, place.name),
+            None => place.name.clone(),
+        };
+        Err(format!("place {} is not in module {}", qualified, name))
+    }
+}
+
+/// Check the names of the parsed input, transform panics on undeclared places
+pub fn validate(input: &RawParserInput) -> Result<(), String> {
+    let input = &instantiate(input.clone())?;
+    if input.modules.is_empty() {
+        return Err("the net has no modules".to_string());
+    }
+    for module in &input.modules {
+        let mut places = HashSet::new();
+        for place in &module.places {
+            if !places.insert(&place.name) {
+                return Err(format!(
+                    "place {} is declared twice in module {}",
+                    place.name, module.name
+                ));
+            }
+        }
+    }
+    let names = Names::new(input);
+    // modules declaring each transition and if it is shared there, None for the block
+    let mut transitions: HashMap<String, Vec<(Option<usize>, bool)>> = HashMap::new();
+    let mut declare = |name: &str, module: Option<usize>, shared: bool| {
+        let previous = transitions.entry(name.to_string()).or_default();
+        // only shared transitions of different modules have the same name
+        if previous.iter().any(|&(m, s)| !(shared && s && m != module)) {
+            let local =
+                module.is_some() && previous.iter().all(|&(m, _)| m.is_some() && m != module);
+            return Err(if local {
+                format!(
+                    "transition {} is declared in several modules, declare it shared",
+                    name
+                )
+            } else {
+                format!("transition {} is declared twice", name)
+            });
+        }
+        previous.push((module, shared));
+        Ok(())
+    };
+    for transition in &input.transitions {
+        declare(&transition.name, None, false)?;
+        let arcs = transition
+            .input_places
+            .iter()
+            .chain(&transition.output_places);
+        for place in arcs {
+            names
+                .resolve(place)
+                .map_err(|e| format!("transition {}: {}", transition.name, e))?;
+        }
+    }
     for (i, module) in input.modules.iter().enumerate() {
-        for place in module.places.iter() {
-            place_to_module.insert(place.name.clone(), i);
+        for transition in &module.transitions {
+            declare(&transition.name, Some(i), transition.shared)?;
+            let arcs = transition
+                .input_places
+                .iter()
+                .chain(&transition.output_places);
+            for place in arcs {
+                names
+                    .resolve_in(place, i)
+                    .map_err(|e| format!("transition {}: {}", transition.name, e))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Compose the files of a net, usually a file and the ones it includes
+/// the modules are appended, transitions of the blocks with the same name are one transition
+/// with the arcs of all files, shared transitions of the modules are matched by transform
+pub fn merge(inputs: impl IntoIterator<Item = RawParserInput>) -> RawParserInput {
+    let mut merged = RawParserInput {
+        includes: vec![],
+        comments: vec![],
+        transitions: vec![],
+        closing: vec![],
+        modules: vec![],
+        end: vec![],
+    };
+    for input in inputs {
+        for transition in input.transitions {
+            match merged
+                .transitions
+                .iter_mut()
+                .find(|t| t.name == transition.name)
+            {
+                Some(t) => {
+                    t.input_places.extend(transition.input_places);
+                    t.output_places.extend(transition.output_places);
+                }
+                None => merged.transitions.push(transition),
+            }
+        }
+        merged.modules.extend(input.modules);
+    }
+    merged
+}
+
+/// Instantiate the module templates, Name[i] for i in a..b gives the modules Name_a .. Name_<b - 1>
+/// every name with an index gets _ and its value appended and the weights are evaluated
+pub fn instantiate(input: RawParserInput) -> Result<RawParserInput, String> {
+    let mut modules = vec![];
+    for module in &input.modules {
+        match &module.template {
+            None => modules.push(instantiate_module(module, None)?),
+            Some(template) => {
+                for i in template.start..template.end {
+                    let binding = Some((template.variable.as_str(), i));
+                    modules.push(instantiate_module(module, binding)?);
+                }
+            }
+        }
+    }
+    let transitions = input
+        .transitions
+        .iter()
+        .map(|t| instantiate_transition(t, None))
+        .collect::<Result<_, _>>()?;
+    Ok(RawParserInput {
+        transitions,
+        modules,
+        ..input
+    })
+}
+
+/// the variable of a template and its value
+type Binding<'a> = Option<(&'a str, u32)>;
+
+fn instantiate_module(
+    module: &RawParserModule,
+    binding: Binding,
+) -> Result<RawParserModule, String> {
+    let name = match binding {
+        Some((_, i)) => format!("{}_{}", module.name, i),
+        None => module.name.clone(),
+    };
+    let in_module = |e: String| format!("module {}: {}", name, e);
+    let places = module
+        .places
+        .iter()
+        .map(|p| instantiate_place(p, binding))
+        .collect::<Result<_, _>>()
+        .map_err(in_module)?;
+    let transitions = module
+        .transitions
+        .iter()
+        .map(|t| instantiate_transition(t, binding))
+        .collect::<Result<_, _>>()
+        .map_err(in_module)?;
+    Ok(RawParserModule {
+        name,
+        template: None,
+        places,
+        transitions,
+        ..module.clone()
+    })
+}
+
+fn instantiate_transition(
+    transition: &RawParserTransition,
+    binding: Binding,
+) -> Result<RawParserTransition, String> {
+    let name = match &transition.index {
+        Some(index) => format!("{}_{}", transition.name, evaluate(index, binding)?),
+        None => transition.name.clone(),
+    };
+    let arcs = |places: &[RawParserPlace]| {
+        places
+            .iter()
+            .map(|p| instantiate_place(p, binding))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("transition {}: {}", name, e))
+    };
+    Ok(RawParserTransition {
+        input_places: arcs(&transition.input_places)?,
+        output_places: arcs(&transition.output_places)?,
+        index: None,
+        name: name.clone(),
+        ..transition.clone()
+    })
+}
+
+fn instantiate_place(place: &RawParserPlace, binding: Binding) -> Result<RawParserPlace, String> {
+    let mut place = place.clone();
+    if let Some(expr) = place.weight_expr.take() {
+        let weight = evaluate(&expr, binding)?;
+        place.weight = weight
+            .try_into()
+            .map_err(|_| format!("weight {} of place {} is too large", weight, place.name))?;
+    }
+    Ok(place)
+}
+
+/// value of the expression, the variable is only known in a template
+fn evaluate(expr: &RawParserExpr, binding: Binding) -> Result<u32, String> {
+    match expr {
+        RawParserExpr::Number(n) => Ok(*n),
+        RawParserExpr::Variable(v) => match binding {
+            Some((variable, i)) if variable == v => Ok(i),
+            _ => Err(format!("unknown variable {}", v)),
+        },
+        RawParserExpr::Binary(left, op, right) => {
+            let (l, r) = (evaluate(left, binding)?, evaluate(right, binding)?);
+            let value = match op {
+                RawParserOp::Add => l.checked_add(r),
+                RawParserOp::Sub => l.checked_sub(r),
+                RawParserOp::Mul => l.checked_mul(r),
+                RawParserOp::Div => l.checked_div(r),
+                RawParserOp::Rem => l.checked_rem(r),
+                RawParserOp::Eq => Some((l == r) as u32),
+                RawParserOp::Ne => Some((l != r) as u32),
+                RawParserOp::Lt => Some((l < r) as u32),
+                RawParserOp::Le => Some((l <= r) as u32),
+                RawParserOp::Gt => Some((l > r) as u32),
+                RawParserOp::Ge => Some((l >= r) as u32),
+            };
+            let at = binding.map_or(String::new(), |(v, i)| format!(" for {} = {}", v, i));
+            value.ok_or(format!("{} is out of range{}", expr, at))
         }
     }
+}
+
+pub fn transform(input: RawParserInput) -> ModularPetrinet {
+    let input = instantiate(input).unwrap_or_else(|e| panic!("Template: {}", e));
+    // build place -> module Map
+    let names = Names::new(&input);
 
     // find all interface transitions split transitions to modules
     let mut interface_transitions = Vec::new();
     let mut module_transitions = vec![vec![]; input.modules.len()];
     for transition in input.transitions.iter() {
-        let belongs_to = transition_belongs_module(transition, &place_to_module);
+        let belongs_to = transition_belongs_module(transition, &names);
         if belongs_to.len() > 1 {
             // extern transition
             let id = interface_transitions.len() as u32;
@@ -31,6 +317,22 @@ pub fn transform(input: RawParserInput) -> ModularPetrinet {
             module_transitions[module_id as usize].push(transition);
         }
     }
+    // shared transitions declared in several modules, the others are intern
+    let mut shared: HashMap<&str, usize> = HashMap::new();
+    for transition in input.modules.iter().flat_map(|m| &m.transitions) {
+        if transition.shared {
+            *shared.entry(&transition.name).or_default() += 1;
+        }
+    }
+    for transition in input.modules.iter().flat_map(|m| &m.transitions) {
+        let name = &transition.name;
+        if shared.get(name.as_str()) > Some(&1)
+            && !interface_transitions.iter().any(|(n, _)| n == name)
+        {
+            let id = interface_transitions.len() as u32;
+            interface_transitions.push((name.clone(), id));
+        }
+    }
 
     let intern_transition_start = interface_transitions.len() as u32;
 
@@ -42,6 +344,7 @@ pub fn transform(input: RawParserInput) -> ModularPetrinet {
             &module_transitions,
             id as u16,
             &interface_transitions,
+            &names,
         );
         modules.push(module);
         markings.push(marking);
@@ -70,10 +373,12 @@ pub fn transform(input: RawParserInput) -> ModularPetrinet {
 
 fn build_module(
     m: &RawParserModule,
-    t: &Vec<Vec<&RawParserTransition>>,
+    t: &[Vec<&RawParserTransition>],
     id: u16,
-    it: &Vec<(String, u32)>,
+    it: &[(String, u32)],
+    names: &Names,
 ) -> (PetrinetModul, Marking) {
+    let m_id = id as usize;
     // println!("Build Moule: {}", id);
     let mut places = vec![];
     let mut marking = Marking {
@@ -111,7 +416,9 @@ fn build_module(
         })
     }
 
-    for raw_transition in t[id as usize].iter() {
+    // transitions of the block, then the ones declared in the module
+    let block = t[id as usize].iter().map(|t| (*t, false));
+    for (raw_transition, local) in block.chain(m.transitions.iter().map(|t| (t, true))) {
         // println!("Transition: {}", raw_transition.name);
         // get id
         let id;
@@ -131,10 +438,12 @@ fn build_module(
         };
 
         // add all input_places
-        transition.input_places = collect_all_places(&raw_transition.input_places, &places_id_map);
+        let module = (!local).then_some(m_id);
+        transition.input_places =
+            collect_all_places(&raw_transition.input_places, &places_id_map, names, module);
         // add all output_places
         transition.output_places =
-            collect_all_places(&raw_transition.output_places, &places_id_map);
+            collect_all_places(&raw_transition.output_places, &places_id_map, names, module);
 
         // add to places
         // inpute places
@@ -170,12 +479,19 @@ fn build_module(
     )
 }
 
+/// the places of the transition which belong to the module
+/// all of them for a transition declared in the module, given as None
 fn collect_all_places(
     places: &Vec<RawParserPlace>,
     p_map: &HashMap<String, usize>,
+    names: &Names,
+    module: Option<usize>,
 ) -> Vec<(u32, Weight)> {
     let mut transition_places = vec![];
     for p in places {
+        if module.is_some_and(|m| names.resolve(p) != Ok(m)) {
+            continue;
+        }
         if let Some(id) = p_map.get(&p.name) {
             transition_places.push((*id as u32, p.weight as Weight));
         }
@@ -185,10 +501,7 @@ fn collect_all_places(
 }
 
 /// returns to which specific modules a transition belongs
-fn transition_belongs_module(
-    transition: &RawParserTransition,
-    place_to_module: &HashMap<String, usize>,
-) -> HashSet<u32> {
+fn transition_belongs_module(transition: &RawParserTransition, names: &Names) -> HashSet<u32> {
     let mut module_belonging = HashSet::new();
     let places = transition
         .input_places
@@ -196,16 +509,195 @@ fn transition_belongs_module(
         .chain(transition.output_places.iter());
 
     for place in places {
-        let module_id = place_to_module.get(&place.name);
-
-        match module_id {
-            Some(x) => {
-                module_belonging.insert(*x as u32);
+        match names.resolve(place) {
+            Ok(x) => {
+                module_belonging.insert(x as u32);
             }
-            None => {
-                panic!("Place: {} belongs to no module", place.name)
+            Err(e) => {
+                panic!("Place: {}", e)
             }
         }
     }
     module_belonging
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{format_input::fmt_net, parse_input::parse},
+        testing::{example, read},
+    };
+
+    use super::{merge, transform, validate};
+
+    #[test]
+    fn test_validate() {
+        let input = read("ba2022/P006a.pnawir");
+        assert_eq!(validate(&parse(&input).unwrap().1), Ok(()));
+
+        let input = "{\n    t: p1 -> p3\n}\n\nM1 {\n    p1(1), p2\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("transition t: place p3 belongs to no module".to_string())
+        );
+        let input = "{\n    t: p1 -> p2\n}\n\nM1 {\n    p1(1), p2\n}\n\nM2 {\n    p2\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err(
+                "transition t: place p2 is declared in several modules, qualify it as Module.p2"
+                    .to_string()
+            )
+        );
+        let input = "{\n    t: p1 -> M3.p2\n}\n\nM1 {\n    p1(1), p2\n}\n\nM2 {\n    p2\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("transition t: module M3 does not exist".to_string())
+        );
+        let input = "{\n    t: p1 -> p2\n}\n\nM1 {\n    p1(1), p2, p1\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("place p1 is declared twice in module M1".to_string())
+        );
+        // a module name may repeat as long as the qualified places stay unique
+        let input = "M1 {\n    p1(1)\n}\n\nM2 {\n    p2\n}\n\nM1 {\n    p1\n}\n";
+        assert_eq!(validate(&parse(input).unwrap().1), Ok(()));
+        let input = format!("{{\n    t: M1.p1 -> M2.p2\n}}\n\n{}", input);
+        assert_eq!(
+            validate(&parse(&input).unwrap().1),
+            Err("transition t: place M1.p1 is ambiguous, module M1 is declared twice".to_string())
+        );
+        let input = read("ba2022/P006a.pnawir");
+        assert_eq!(validate(&parse(&input).unwrap().1), Ok(()));
+    }
+
+    #[test]
+    fn test_qualified_places() {
+        // both modules have a place p, the qualified arcs connect them
+        let input = "{\n    a: M1.p -> M1.q\n    t: M1.q -> M2.p\n}\n\n\
+            M1 {\n    p(1), q\n}\n\nM2 {\n    p\n}\n";
+        let raw = parse(input).unwrap().1;
+        assert_eq!(validate(&raw), Ok(()));
+        let net = transform(raw);
+        assert_eq!(net.intern_transition_start, 1);
+        assert_eq!(net.extern_t_overview, vec![vec![0, 1]]);
+        let t = &net.modules[1].transitions[0];
+        assert_eq!((t.input_places.len(), t.output_places.len()), (0, 1));
+        assert_eq!(net.modules[0].transitions[1].name, "a");
+        assert_eq!(net.find_place("M2.p"), Ok((1, 0)));
+        assert_eq!(net.find_place("q"), Ok((0, 1)));
+        assert_eq!(
+            net.find_place("p"),
+            Err("place p is ambiguous, qualify it as Module.p".to_string())
+        );
+        let p006a = example("ba2022/P006a.pnawir");
+        assert_eq!(p006a.find_place("N2.j1"), Ok((2, 0)));
+        let input = "M1 {\n    p\n}\n\nM1 {\n    p\n}\n";
+        assert_eq!(
+            transform(parse(input).unwrap().1).find_place("M1.p"),
+            Err("place M1.p is ambiguous, module M1 is declared twice".to_string())
+        );
+        assert_eq!(
+            net.find_place("M3.p"),
+            Err("unknown place: M3.p".to_string())
+        );
+        assert_eq!(net.place_name(1, 0), "M2.p");
+        assert_eq!(net.place_name(0, 1), "q");
+    }
+
+    #[test]
+    fn test_merge() {
+        // P001 split into two files, the interface transitions have arcs in both
+        let m1 = "{\n    v1: p1 -> p2\n    v2: p3 -> p1(2)\n    t: p2 -> p3\n    u: p3 ->\n}\n\n\
+            M1 {\n    p1(1), p2, p3\n}\n";
+        let m2 = "{\n    w1: q2 -> q3\n    w2: q3 -> q2\n    t: q1 -> q2\n    u: q2 -> q4\n}\n\n\
+            M2 {\n    q1(1), q2, q3, q4\n}\n";
+        let merged = merge([parse(m1).unwrap().1, parse(m2).unwrap().1]);
+        assert_eq!(validate(&merged), Ok(()));
+        assert_eq!(
+            fmt_net(&transform(merged)),
+            fmt_net(&example("ba2022/P001.pnawir"))
+        );
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let input = read("templates/philosophers.pnawir");
+        let raw = parse(&input).unwrap().1;
+        assert_eq!(validate(&raw), Ok(()));
+        let net = transform(raw);
+        let names = net
+            .modules
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names[..2], ["Phil_0", "Phil_1"]);
+        assert_eq!(names[9], "Fork_4");
+        assert_eq!(net.intern_transition_start, 15);
+        // philosopher 0 takes fork 0 first, then fork 1
+        assert_eq!(net.find_transition("left_0"), Some((0, 0)));
+        assert_eq!(net.extern_t_overview[0], vec![0, 5]);
+        assert_eq!(net.extern_t_overview[1], vec![0, 6]);
+        assert_eq!(net.extern_t_overview[2], vec![0, 5, 6]);
+
+        let input = "F[i] for i in 0..2 {\n    p(i - 1)\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("module F_0: i - 1 is out of range for i = 0".to_string())
+        );
+        let input = "{\n    t[j]: p ->\n}\n\nM {\n    p\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("unknown variable j".to_string())
+        );
+
+        // the index is separated, t1[i] for i = 1 and t[i] for i = 11 differ
+        let input = "M[i] for i in 0..12 {\n    p\n    t1[i]: p ->\n    t[i]: -> p\n}\n";
+        let net = transform(parse(input).unwrap().1);
+        assert_eq!(net.find_transition("t1_1"), Some((1, 0)));
+        assert_eq!(net.find_transition("t_11"), Some((11, 1)));
+        // an instance has the name of another module, only A_1.p is ambiguous
+        let input = "{\n    t: A_1.q -> A_0.p\n}\n\n\
+            A[i] for i in 0..2 {\n    p\n}\n\nA_1 {\n    p, q\n}\n";
+        assert_eq!(validate(&parse(input).unwrap().1), Ok(()));
+        let input = input.replace("A_0.p", "A_1.p");
+        assert_eq!(
+            validate(&parse(&input).unwrap().1),
+            Err("transition t: place A_1.p is ambiguous, module A_1 is declared twice".to_string())
+        );
+        let input =
+            "M[i] for i in 0..2 {\n    p\n    t[i]: p ->\n}\n\nN {\n    q\n    t_1: q ->\n}\n";
+        assert_eq!(
+            validate(&parse(input).unwrap().1),
+            Err("transition t_1 is declared in several modules, declare it shared".to_string())
+        );
+    }
+
+    #[test]
+    fn test_module_transitions() {
+        // P001 with the transitions declared in the modules
+        let input = "M1 {\n    p1(1), p2, p3\n    v1: p1 -> p2\n    v2: p3 -> p1(2)\n\
+            shared t: p2 -> p3\n    shared u: p3 ->\n}\n\n\
+            M2 {\n    q1(1), q2, q3, q4\n    w1: q2 -> q3\n    w2: q3 -> q2\n\
+            shared t: q1 -> q2\n    shared u: q2 -> q4\n}\n";
+        let raw = parse(input).unwrap().1;
+        assert_eq!(validate(&raw), Ok(()));
+        assert_eq!(
+            fmt_net(&transform(raw)),
+            fmt_net(&example("ba2022/P001.pnawir"))
+        );
+
+        let validate_str = |input: &str| validate(&parse(input).unwrap().1);
+        assert_eq!(
+            validate_str("M1 {\n    p\n    t: p ->\n}\n\nM2 {\n    q\n    t: q ->\n}\n"),
+            Err("transition t is declared in several modules, declare it shared".to_string())
+        );
+        assert_eq!(
+            validate_str("M1 {\n    p\n    shared t: p ->\n    shared t: -> p\n}\n"),
+            Err("transition t is declared twice".to_string())
+        );
+        assert_eq!(
+            validate_str("M1 {\n    p\n    t: p -> q\n}\n\nM2 {\n    q\n}\n"),
+            Err("transition t: place q is not in module M1".to_string())
+        );
+    }
+}
@@ -0,0 +1,5 @@
+pub mod classification;
+pub mod flat_net;
+pub mod invariants;
+pub mod siphons;
+pub mod state_equation;
@@ -0,0 +1,127 @@
+use std::fmt;
+
+use super::flat_net::FlatNet;
+
+/// Structural net classes
+/// all classes except ordinary assume an ordinary net
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification {
+    /// all arc weights are 1
+    pub ordinary: bool,
+    /// every transition has exactly one input and one output place
+    pub state_machine: bool,
+    /// every place has exactly one input and one output transition
+    pub marked_graph: bool,
+    /// every place with more than one output transition is the only input of those transitions
+    pub free_choice: bool,
+    /// places sharing an output transition have the same output transitions
+    pub extended_free_choice: bool,
+    /// output transitions of places are equal, disjoint or contained in each other
+    pub asymmetric_choice: bool,
+}
+
+pub fn classify(flat: &FlatNet) -> Classification {
+    let places = 0..flat.places.len();
+    let transitions = 0..flat.transitions.len();
+    let outputs = places
+        .clone()
+        .map(|p| {
+            transitions
+                .clone()
+                .filter(|&t| flat.pre[p][t] > 0)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let inputs = places
+        .clone()
+        .map(|p| {
+            transitions
+                .clone()
+                .filter(|&t| flat.post[p][t] > 0)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let ordinary = flat
+        .pre
+        .iter()
+        .chain(flat.post.iter())
+        .all(|row| row.iter().all(|&w| w <= 1));
+    let state_machine = transitions
+        .clone()
+        .all(|t| flat.preset(t).len() == 1 && flat.postset(t).len() == 1);
+    let marked_graph = places
+        .clone()
+        .all(|p| inputs[p].len() == 1 && outputs[p].len() == 1);
+    let free_choice = places
+        .clone()
+        .all(|p| outputs[p].len() <= 1 || outputs[p].iter().all(|&t| flat.preset(t) == [p]));
+
+    let mut extended_free_choice = true;
+    let mut asymmetric_choice = true;
+    for p in places.clone() {
+        for q in places.clone() {
+            let (a, b) = (&outputs[p], &outputs[q]);
+            if !a.iter().any(|t| b.contains(t)) {
+                continue;
+            }
+            let a_in_b = a.iter().all(|t| b.contains(t));
+            let b_in_a = b.iter().all(|t| a.contains(t));
+            extended_free_choice &= a_in_b && b_in_a;
+            asymmetric_choice &= a_in_b || b_in_a;
+        }
+    }
+
+    Classification {
+        ordinary,
+        state_machine: ordinary && state_machine,
+        marked_graph: ordinary && marked_graph,
+        free_choice: ordinary && free_choice,
+        extended_free_choice: ordinary && extended_free_choice,
+        asymmetric_choice: ordinary && asymmetric_choice,
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let classes = [
+            (self.state_machine, "state machine"),
+            (self.marked_graph, "marked graph"),
+            (self.free_choice, "free choice"),
+            (self.extended_free_choice, "extended free choice"),
+            (self.asymmetric_choice, "asymmetric choice"),
+            (self.ordinary, "ordinary"),
+        ];
+        let names = classes
+            .iter()
+            .filter(|c| c.0)
+            .map(|c| c.1)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            write!(f, "general")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{structural::flat_net::FlatNet, testing::example};
+
+    use super::classify;
+
+    #[test]
+    fn test_classify() {
+        // t1: b1 -> a1, t2: b1 -> a2
+        let net = example("ba2022/P005.pnawir");
+        let c = classify(&FlatNet::new(&net));
+        assert!(c.state_machine && c.free_choice && c.ordinary);
+        assert!(!c.marked_graph);
+
+        // t11: p2 -> p1(2)
+        let net = example("ba2022/P003.pnawir");
+        let c = classify(&FlatNet::new(&net));
+        assert!(!c.ordinary && !c.free_choice);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::modular_net::{ModularPetrinet, ModuleId, PlaceId, TransitionId, Weight};
+
+/// Composed net (or a single module) with global indexes
+/// places of the modules one after another,
+/// extern transitions first (same ids as in the modules), then the intern transitions of every module
+/// transitions without arcs in the included modules are left out
+#[derive(Debug)]
+pub struct FlatNet {
+    pub places: Vec<(ModuleId, PlaceId)>,
+    pub transitions: Vec<(ModuleId, TransitionId)>,
+    /// pre[p][t], arc weight from place p to transition t
+    pub pre: Vec<Vec<Weight>>,
+    /// post[p][t], arc weight from transition t to place p
+    pub post: Vec<Vec<Weight>>,
+    pub initial: Vec<Weight>,
+    place_offsets: Vec<Option<usize>>,
+    transition_lookup: Vec<Vec<Option<usize>>>,
+}
+
+impl FlatNet {
+    /// all modules composed
+    pub fn new(net: &ModularPetrinet) -> Self {
+        let modules = net.modules.iter().map(|m| m.id).collect::<Vec<_>>();
+        Self::build(net, &modules)
+    }
+
+    /// a single module on its own, extern transitions only with their local arcs
+    pub fn module(net: &ModularPetrinet, m_id: ModuleId) -> Self {
+        Self::build(net, &[m_id])
+    }
+
+    fn build(net: &ModularPetrinet, modules: &[ModuleId]) -> Self {
+        let included = |m_id: &ModuleId| modules.contains(m_id);
+        let exists = |m_id: ModuleId, t_id: TransitionId| {
+            let t = &net.modules[m_id as usize].transitions[t_id as usize];
+            t.input_places.len() + t.output_places.len() > 0
+        };
+
+        let mut places = vec![];
+        let mut place_offsets = vec![None; net.modules.len()];
+        for module in net.modules.iter().filter(|m| included(&m.id)) {
+            place_offsets[module.id as usize] = Some(places.len());
+            places.extend(module.places.iter().map(|p| (module.id, p.id)));
+        }
+
+        let mut transitions = vec![];
+        let mut transition_lookup = net
+            .modules
+            .iter()
+            .map(|m| vec![None; m.transitions.len()])
+            .collect::<Vec<_>>();
+        for (t_id, t_modules) in net.extern_t_overview.iter().enumerate() {
+            let t_id = t_id as TransitionId;
+            let local = t_modules
+                .iter()
+                .filter(|m| included(m))
+                .copied()
+                .collect::<Vec<_>>();
+            if local.is_empty() {
+                continue;
+            }
+            for &m_id in &local {
+                transition_lookup[m_id as usize][t_id as usize] = Some(transitions.len());
+            }
+            transitions.push((local[0], t_id));
+        }
+        for module in net.modules.iter().filter(|m| included(&m.id)) {
+            let intern = &module.transitions[net.intern_transition_start as usize..];
+            for t in intern.iter().filter(|t| exists(module.id, t.id)) {
+                transition_lookup[module.id as usize][t.id as usize] = Some(transitions.len());
+                transitions.push((module.id, t.id));
+            }
+        }
+
+        let mut flat = FlatNet {
+            pre: vec![vec![0; transitions.len()]; places.len()],
+            post: vec![vec![0; transitions.len()]; places.len()],
+            initial: vec![0; places.len()],
+            places,
+            transitions,
+            place_offsets,
+            transition_lookup,
+        };
+
+        for module in net.modules.iter().filter(|m| included(&m.id)) {
+            for t in &module.transitions {
+                let Some(t_idx) = flat.transition_index(module.id, t.id) else {
+                    continue;
+                };
+                for &(p_id, w) in &t.input_places {
+                    let p_idx = flat.place_index(module.id, p_id);
+                    flat.pre[p_idx][t_idx] += w;
+                }
+                for &(p_id, w) in &t.output_places {
+                    let p_idx = flat.place_index(module.id, p_id);
+                    flat.post[p_idx][t_idx] += w;
+                }
+            }
+            for pc in &net.markings[module.id as usize].place_counts {
+                let p_idx = flat.place_index(module.id, pc.place_id);
+                flat.initial[p_idx] = pc.count;
+            }
+        }
+
+        flat
+    }
+
+    /// panics if the module is not part of this net
+    pub fn place_index(&self, m_id: ModuleId, p_id: PlaceId) -> usize {
+        self.place_offsets[m_id as usize].expect("module is part of the net") + p_id as usize
+    }
+
+    /// None if the transition has no arcs in the included modules
+    pub fn transition_index(&self, m_id: ModuleId, t_id: TransitionId) -> Option<usize> {
+        self.transition_lookup[m_id as usize][t_id as usize]
+    }
+
+    /// incidence matrix C = post - pre, C[p][t]
+    pub fn incidence(&self) -> Vec<Vec<i64>> {
+        self.pre
+            .iter()
+            .zip(self.post.iter())
+            .map(|(pre, post)| {
+                pre.iter()
+                    .zip(post.iter())
+                    .map(|(&a, &b)| b as i64 - a as i64)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// input places of a transition
+    pub fn preset(&self, t: usize) -> Vec<usize> {
+        (0..self.places.len())
+            .filter(|&p| self.pre[p][t] > 0)
+            .collect()
+    }
+
+    /// output places of a transition
+    pub fn postset(&self, t: usize) -> Vec<usize> {
+        (0..self.places.len())
+            .filter(|&p| self.post[p][t] > 0)
+            .collect()
+    }
+
+    pub fn place_name(&self, net: &ModularPetrinet, p: usize) -> String {
+        let (m_id, p_id) = self.places[p];
+        net.place_name(m_id, p_id)
+    }
+
+    pub fn transition_name<'a>(&self, net: &'a ModularPetrinet, t: usize) -> &'a str {
+        let (m_id, t_id) = self.transitions[t];
+        net.transition_name(m_id, t_id)
+    }
+}
@@ -0,0 +1,283 @@
+use std::io::{self, Write};
+
+use crate::modular_net::{ModularPetrinet, ModuleId};
+
+use super::flat_net::FlatNet;
+
+/// Semi-positive invariant with minimal support
+/// weights are indexed like the places (P-invariant) or transitions (T-invariant) of the FlatNet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invariant {
+    pub weights: Vec<u64>,
+}
+
+impl Invariant {
+    pub fn support(&self) -> Vec<usize> {
+        (0..self.weights.len())
+            .filter(|&i| self.weights[i] > 0)
+            .collect()
+    }
+
+    /// modules whose places are covered by this P-invariant
+    pub fn covered_modules(&self, flat: &FlatNet) -> Vec<ModuleId> {
+        let mut modules = self
+            .support()
+            .iter()
+            .map(|&p| flat.places[p].0)
+            .collect::<Vec<_>>();
+        modules.dedup();
+        modules
+    }
+
+    /// weighted token sum in the initial marking, constant in every reachable marking
+    /// saturates at u64::MAX, which keeps the bounds derived from it sound
+    pub fn token_sum(&self, flat: &FlatNet) -> u64 {
+        self.weights
+            .iter()
+            .zip(flat.initial.iter())
+            .fold(0u64, |sum, (&w, &m)| {
+                sum.saturating_add(w.saturating_mul(m as u64))
+            })
+    }
+}
+
+/// y * C = 0, y >= 0
+/// Err if the coefficients overflow during the elimination
+pub fn p_invariants(flat: &FlatNet) -> Result<Vec<Invariant>, String> {
+    farkas(&flat.incidence())
+}
+
+/// C * x = 0, x >= 0
+/// Err if the coefficients overflow during the elimination
+pub fn t_invariants(flat: &FlatNet) -> Result<Vec<Invariant>, String> {
+    let c = flat.incidence();
+    let transposed = (0..flat.transitions.len())
+        .map(|t| c.iter().map(|row| row[t]).collect())
+        .collect::<Vec<_>>();
+    farkas(&transposed)
+}
+
+/// Upper bound of every place derived from the P-invariants
+/// None if no invariant covers the place, the net is structurally bounded if all are Some
+pub fn place_bounds(flat: &FlatNet, invariants: &[Invariant]) -> Vec<Option<u64>> {
+    let mut bounds = vec![None; flat.places.len()];
+    for invariant in invariants {
+        let sum = invariant.token_sum(flat);
+        for p in invariant.support() {
+            let bound = sum / invariant.weights[p];
+            bounds[p] = Some(bounds[p].map_or(bound, |b: u64| b.min(bound)));
+        }
+    }
+    bounds
+}
+
+type Row = (Vec<i128>, Vec<i128>);
+
+/// Farkas algorithm, minimal support semi-positive solutions of y * matrix = 0
+fn farkas(matrix: &[Vec<i64>]) -> Result<Vec<Invariant>, String> {
+    let n = matrix.len();
+    let cols = matrix.first().map_or(0, |r| r.len());
+
+    // (remaining matrix row, identity part)
+    let mut rows = (0..n)
+        .map(|i| {
+            let mut unit = vec![0; n];
+            unit[i] = 1;
+            (matrix[i].iter().map(|&x| x as i128).collect(), unit)
+        })
+        .collect::<Vec<Row>>();
+
+    for j in 0..cols {
+        let mut next = vec![];
+        let mut positive = vec![];
+        let mut negative = vec![];
+        for row in rows {
+            match row.0[j].signum() {
+                0 => next.push(row),
+                1 => positive.push(row),
+                _ => negative.push(row),
+            }
+        }
+        // combine every pair with opposite sign so column j becomes zero
+        for a in &positive {
+            for b in &negative {
+                let fa = -b.0[j];
+                let fb = a.0[j];
+                let mut row = (combine(&a.0, &b.0, fa, fb)?, combine(&a.1, &b.1, fa, fb)?);
+                normalize(&mut row);
+                next.push(row);
+            }
+        }
+        rows = minimal_support(next);
+    }
+
+    rows.into_iter()
+        .map(|(_, y)| {
+            let weights = y
+                .into_iter()
+                .map(|w| u64::try_from(w).map_err(|_| overflow()))
+                .collect::<Result<_, _>>()?;
+            Ok(Invariant { weights })
+        })
+        .collect()
+}
+
+/// fa * a + fb * b
+fn combine(a: &[i128], b: &[i128], fa: i128, fb: i128) -> Result<Vec<i128>, String> {
+    a.iter()
+        .zip(b)
+        .map(|(&x, &y)| {
+            fa.checked_mul(x)
+                .zip(fb.checked_mul(y))
+                .and_then(|(x, y)| x.checked_add(y))
+                .filter(|&z| z != i128::MIN)
+                .ok_or_else(overflow)
+        })
+        .collect()
+}
+
+fn overflow() -> String {
+    "invariant coefficients overflow".to_string()
+}
+
+/// divides the row by the gcd of its entries
+fn normalize(row: &mut Row) {
+    let g = row
+        .0
+        .iter()
+        .chain(row.1.iter())
+        .fold(0, |g, &x| gcd(g, x.abs()));
+    if g > 1 {
+        row.0
+            .iter_mut()
+            .chain(row.1.iter_mut())
+            .for_each(|x| *x /= g);
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// drop rows whose support contains the support of another row
+fn minimal_support(rows: Vec<Row>) -> Vec<Row> {
+    let supports = rows
+        .iter()
+        .map(|r| r.1.iter().map(|&x| x != 0).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let contains = |a: &[bool], b: &[bool]| a.iter().zip(b).all(|(&x, &y)| x || !y);
+
+    rows.into_iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            !supports.iter().enumerate().any(|(k, s)| {
+                k != *i && contains(&supports[*i], s) && (!contains(s, &supports[*i]) || k < *i)
+            })
+        })
+        .map(|(_, r)| r)
+        .collect()
+}
+
+pub fn write_p_invariants(
+    net: &ModularPetrinet,
+    flat: &FlatNet,
+    invariants: &[Invariant],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out, "P-Invariants")?;
+    for invariant in invariants {
+        let terms = invariant
+            .support()
+            .iter()
+            .map(|&p| format_term(invariant.weights[p], &flat.place_name(net, p)))
+            .collect::<Vec<_>>();
+        let modules = invariant
+            .covered_modules(flat)
+            .iter()
+            .map(|&m| net.modules[m as usize].name.as_str())
+            .collect::<Vec<_>>();
+        writeln!(
+            out,
+            "  {} = {} | Modules: {}",
+            terms.join(" + "),
+            invariant.token_sum(flat),
+            modules.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_t_invariants(
+    net: &ModularPetrinet,
+    flat: &FlatNet,
+    invariants: &[Invariant],
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    writeln!(out, "T-Invariants")?;
+    for invariant in invariants {
+        let terms = invariant
+            .support()
+            .iter()
+            .map(|&t| format_term(invariant.weights[t], flat.transition_name(net, t)))
+            .collect::<Vec<_>>();
+        writeln!(out, "  {}", terms.join(" + "))?;
+    }
+    Ok(())
+}
+
+fn format_term(weight: u64, name: &str) -> String {
+    if weight == 1 {
+        name.to_string()
+    } else {
+        format!("{}*{}", weight, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{structural::flat_net::FlatNet, testing::example};
+
+    use super::{
+        farkas, p_invariants, place_bounds, t_invariants, write_p_invariants, write_t_invariants,
+    };
+
+    #[test]
+    fn test_invariants() {
+        let net = example("ba2022/P006.pnawir");
+        let flat = FlatNet::new(&net);
+
+        // a1 + a2 = 1, b1 + b2 = 1
+        let p = p_invariants(&flat).unwrap();
+        assert_eq!(p.len(), 2);
+        assert!(p.iter().all(|i| i.token_sum(&flat) == 1));
+        assert!(p.iter().all(|i| i.covered_modules(&flat).len() == 1));
+        assert!(place_bounds(&flat, &p).iter().all(|&b| b == Some(1)));
+
+        // t1, t2, u1 + u2, u3 + u4
+        let t = t_invariants(&flat).unwrap();
+        assert_eq!(t.len(), 4);
+
+        let mut out = vec![];
+        write_p_invariants(&net, &flat, &p, &mut out).unwrap();
+        write_t_invariants(&net, &flat, &t, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 8);
+        assert!(text.contains(" = 1 | Modules: "));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // p0 --t0--> 2^40 p1 --t1--> 2^40 p2, y = (2^80, 2^40, 1) does not fit into u64
+        let big = 1i64 << 40;
+        let matrix = vec![vec![-1, 0], vec![big, -1], vec![0, big]];
+        assert!(farkas(&matrix).is_err());
+
+        // rows are divided by their gcd, y = (2, 1)
+        let matrix = vec![vec![-big], vec![2 * big]];
+        assert_eq!(farkas(&matrix).unwrap()[0].weights, vec![2, 1]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crate::modular_net::{ModularPetrinet, Weight};
+
+use super::{classification::classify, flat_net::FlatNet};
+
+/// Siphons and traps are lists of place indexes of the FlatNet
+#[derive(Debug)]
+pub struct SiphonAnalysis {
+    pub siphons: Vec<Vec<usize>>,
+    pub traps: Vec<Vec<usize>>,
+    pub free_choice: bool,
+    /// minimal siphons without an initially marked trap
+    pub unmarked_siphons: Vec<Vec<usize>>,
+}
+
+impl SiphonAnalysis {
+    /// every siphon contains an initially marked trap
+    /// for free choice nets this is equivalent to liveness,
+    /// for ordinary nets it is sufficient for deadlock freedom
+    pub fn commoner(&self) -> bool {
+        self.unmarked_siphons.is_empty()
+    }
+}
+
+pub fn analyze(flat: &FlatNet) -> SiphonAnalysis {
+    let siphons = minimal_siphons(flat);
+    let unmarked_siphons = siphons
+        .iter()
+        .filter(|s| !maximal_trap(flat, s).iter().any(|&p| flat.initial[p] > 0))
+        .cloned()
+        .collect();
+    SiphonAnalysis {
+        traps: minimal_traps(flat),
+        free_choice: classify(flat).free_choice,
+        siphons,
+        unmarked_siphons,
+    }
+}
+
+/// analysis of every module on its own
+pub fn analyze_modules(net: &ModularPetrinet) -> Vec<(FlatNet, SiphonAnalysis)> {
+    net.modules
+        .iter()
+        .map(|m| {
+            let flat = FlatNet::module(net, m.id);
+            let analysis = analyze(&flat);
+            (flat, analysis)
+        })
+        .collect()
+}
+
+/// S is a siphon if every transition putting tokens into S also takes tokens from S
+pub fn minimal_siphons(flat: &FlatNet) -> Vec<Vec<usize>> {
+    minimal_sets(flat, &flat.post, &flat.pre)
+}
+
+/// S is a trap if every transition taking tokens from S also puts tokens into S
+pub fn minimal_traps(flat: &FlatNet) -> Vec<Vec<usize>> {
+    minimal_sets(flat, &flat.pre, &flat.post)
+}
+
+/// largest trap contained in the set, might be empty
+pub fn maximal_trap(flat: &FlatNet, set: &[usize]) -> Vec<usize> {
+    let mut trap = set.to_vec();
+    loop {
+        let before = trap.len();
+        let current = trap.clone();
+        trap.retain(|&p| {
+            (0..flat.transitions.len())
+                .filter(|&t| flat.pre[p][t] > 0)
+                .all(|t| current.iter().any(|&q| flat.post[q][t] > 0))
+        });
+        if trap.len() == before {
+            return trap;
+        }
+    }
+}
+
+/// Branching search for the minimal sets S, where every transition in "into" of S is in "from" of S
+/// a violating transition is fixed by adding one of its "from" places
+fn minimal_sets(flat: &FlatNet, into: &[Vec<Weight>], from: &[Vec<Weight>]) -> Vec<Vec<usize>> {
+    let place_count = flat.places.len();
+    let transition_count = flat.transitions.len();
+    let mut found: Vec<Vec<bool>> = vec![];
+    let mut visited = HashSet::new();
+
+    for p in 0..place_count {
+        let mut start = vec![false; place_count];
+        start[p] = true;
+        let mut to_explore = vec![start];
+
+        while let Some(set) = to_explore.pop() {
+            if !visited.insert(set.clone()) {
+                continue;
+            }
+            if found.iter().any(|f| is_subset(f, &set)) {
+                continue;
+            }
+            let touches =
+                |rows: &[Vec<Weight>], t: usize| (0..place_count).any(|q| set[q] && rows[q][t] > 0);
+            let violating = (0..transition_count).find(|&t| touches(into, t) && !touches(from, t));
+            match violating {
+                None => found.push(set),
+                Some(t) => {
+                    for q in (0..place_count).filter(|&q| from[q][t] > 0) {
+                        let mut next = set.clone();
+                        next[q] = true;
+                        to_explore.push(next);
+                    }
+                }
+            }
+        }
+    }
+
+    found
+        .iter()
+        .filter(|s| !found.iter().any(|f| f != *s && is_subset(f, s)))
+        .map(|s| (0..place_count).filter(|&p| s[p]).collect())
+        .collect()
+}
+
+fn is_subset(a: &[bool], b: &[bool]) -> bool {
+    a.iter().zip(b).all(|(&x, &y)| !x || y)
+}
+
+pub fn write(
+    net: &ModularPetrinet,
+    flat: &FlatNet,
+    analysis: &SiphonAnalysis,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let names = |set: &Vec<usize>| {
+        set.iter()
+            .map(|&p| flat.place_name(net, p))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    writeln!(out, "Siphons")?;
+    for siphon in &analysis.siphons {
+        writeln!(out, "  {{{}}}", names(siphon))?;
+    }
+    writeln!(out, "Traps")?;
+    for trap in &analysis.traps {
+        writeln!(out, "  {{{}}}", names(trap))?;
+    }
+    writeln!(out, "Free Choice: {}", analysis.free_choice)?;
+    writeln!(out, "Commoner: {}", analysis.commoner())?;
+    for siphon in &analysis.unmarked_siphons {
+        writeln!(out, "  no marked trap in {{{}}}", names(siphon))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{structural::flat_net::FlatNet, testing::example};
+
+    use super::{analyze, analyze_modules, write};
+
+    #[test]
+    fn test_siphons_traps() {
+        // t1: b1 -> a1, t2: b1 -> a2
+        let net = example("ba2022/P005.pnawir");
+        let flat = FlatNet::new(&net);
+        let analysis = analyze(&flat);
+
+        let b1 = flat.place_index(0, 0);
+        assert_eq!(analysis.siphons, vec![vec![b1]]);
+        assert_eq!(analysis.traps.len(), 2);
+        assert!(analysis.free_choice);
+        assert!(!analysis.commoner());
+
+        let mut out = vec![];
+        write(&net, &flat, &analysis, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("Commoner: false\n  no marked trap in {"));
+
+        // each module on its own
+        let modules = analyze_modules(&net);
+        assert_eq!(modules[1].1.traps.len(), 2);
+    }
+}
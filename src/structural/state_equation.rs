@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use nom::character::complete::char;
+use nom::multi::separated_list1;
+
+use crate::{
+    model_checking::proposition::{atom, Atom, CmpOp},
+    modular_net::ModularPetrinet,
+    parser::parse_input::ws,
+};
+
+use super::flat_net::FlatNet;
+
+/// upper limit of constraints during the elimination, the result is Unknown above
+const MAX_CONSTRAINTS: usize = 20_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// the state equation has no solution, the target is not reachable
+    Unreachable,
+    /// the state equation has a solution, the target might be reachable
+    MaybeReachable,
+    /// the solver gave up, too many constraints or the coefficients overflow
+    Unknown,
+}
+
+/// Constraint on the target marking, place <op> value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub place: String,
+    pub op: CmpOp,
+    pub value: u32,
+}
+
+/// Parse a conjunction of place constraints
+/// <place> <op> <count> & <place> <op> <count> ...
+pub fn parse_target(input: &str) -> Result<Vec<Target>, String> {
+    let result = separated_list1(ws(char('&')), atom)(input);
+    match result {
+        Ok(("", atoms)) => atoms
+            .into_iter()
+            .map(|a| match a {
+                Atom::PlaceCount { place, op, value } => Ok(Target { place, op, value }),
+                _ => Err(format!("only place constraints are allowed: {:?}", a)),
+            })
+            .collect(),
+        Ok((rest, _)) => Err(format!("unexpected input: {}", rest)),
+        Err(e) => Err(format!("invalid target: {}", e)),
+    }
+}
+
+/// Check if M = M0 + C * x with x >= 0 has a solution satisfying the target
+/// solved over the rationals by Fourier-Motzkin elimination with integer rounding,
+/// so Unreachable is always correct while MaybeReachable is only necessary
+pub fn check_target(net: &ModularPetrinet, target: &[Target]) -> Result<Verdict, String> {
+    let flat = FlatNet::new(net);
+    let n = flat.transitions.len();
+
+    // one row per place: M0(p) + sum of (t, w) in place.input_transitions - output_transitions
+    let mut rows = vec![];
+    for module in &net.modules {
+        for place in &module.places {
+            let mut row = vec![0; n];
+            for &(t_id, w) in &place.input_transitions {
+                if let Some(t) = flat.transition_index(module.id, t_id) {
+                    row[t] += w as i64;
+                }
+            }
+            for &(t_id, w) in &place.output_transitions {
+                if let Some(t) = flat.transition_index(module.id, t_id) {
+                    row[t] -= w as i64;
+                }
+            }
+            let m0 = flat.initial[flat.place_index(module.id, place.id)] as i64;
+            rows.push((row, m0));
+        }
+    }
+
+    // constraints a * x >= b
+    let mut constraints: Vec<(Vec<i64>, i64)> = vec![];
+    let mut at_least =
+        |row: &Vec<i64>, m0: i64, value: i64| constraints.push((row.clone(), value - m0));
+    let mut constrained = vec![false; rows.len()];
+    for t in target {
+        let (m_id, p_id) = net.find_place(&t.place)?;
+        let p = flat.place_index(m_id, p_id);
+        let (row, m0) = &rows[p];
+        let negated = row.iter().map(|x| -x).collect::<Vec<_>>();
+        let v = t.value as i64;
+        match t.op {
+            CmpOp::Ge => at_least(row, *m0, v),
+            CmpOp::Gt => at_least(row, *m0, v + 1),
+            CmpOp::Le => at_least(&negated, -m0, -v),
+            CmpOp::Lt => at_least(&negated, -m0, -(v - 1)),
+            CmpOp::Eq => {
+                at_least(row, *m0, v);
+                at_least(&negated, -m0, -v);
+            }
+            // a disjunction, only non negativity
+            CmpOp::Ne => {
+                at_least(row, *m0, 0);
+            }
+        }
+        constrained[p] = true;
+    }
+    for (p, (row, m0)) in rows.iter().enumerate() {
+        if !constrained[p] {
+            at_least(row, *m0, 0);
+        }
+    }
+    for t in 0..n {
+        let mut unit = vec![0; n];
+        unit[t] = 1;
+        at_least(&unit, 0, 0);
+    }
+
+    Ok(fourier_motzkin(constraints, n))
+}
+
+fn fourier_motzkin(mut constraints: Vec<(Vec<i64>, i64)>, n: usize) -> Verdict {
+    let mut eliminated = vec![false; n];
+    for _ in 0..n {
+        constraints = simplify(constraints);
+        if constraints
+            .iter()
+            .any(|(a, b)| a.iter().all(|&x| x == 0) && *b > 0)
+        {
+            return Verdict::Unreachable;
+        }
+
+        // variable with the fewest new constraints
+        let count = |j: usize| {
+            let pos = constraints.iter().filter(|c| c.0[j] > 0).count();
+            let neg = constraints.iter().filter(|c| c.0[j] < 0).count();
+            pos * neg
+        };
+        let Some(j) = (0..n).filter(|&j| !eliminated[j]).min_by_key(|&j| count(j)) else {
+            break;
+        };
+        eliminated[j] = true;
+        if count(j) + constraints.len() > MAX_CONSTRAINTS {
+            return Verdict::Unknown;
+        }
+
+        let mut next = vec![];
+        let mut positive = vec![];
+        let mut negative = vec![];
+        for c in constraints {
+            match c.0[j].signum() {
+                0 => next.push(c),
+                1 => positive.push(c),
+                _ => negative.push(c),
+            }
+        }
+        for p in &positive {
+            for q in &negative {
+                let fp = -q.0[j];
+                let fq = p.0[j];
+                let combined = p.0.iter().zip(&q.0).map(|(&x, &y)| combine(fp, x, fq, y));
+                let Some(a) = combined.collect::<Option<_>>() else {
+                    return Verdict::Unknown;
+                };
+                let Some(b) = combine(fp, p.1, fq, q.1) else {
+                    return Verdict::Unknown;
+                };
+                next.push((a, b));
+            }
+        }
+        constraints = next;
+    }
+
+    constraints = simplify(constraints);
+    if constraints
+        .iter()
+        .any(|(a, b)| a.iter().all(|&x| x == 0) && *b > 0)
+    {
+        Verdict::Unreachable
+    } else {
+        Verdict::MaybeReachable
+    }
+}
+
+/// fp * x + fq * y, None on overflow
+fn combine(fp: i64, x: i64, fq: i64, y: i64) -> Option<i64> {
+    fp.checked_mul(x)?
+        .checked_add(fq.checked_mul(y)?)
+        .filter(|&z| z != i64::MIN)
+}
+
+/// divide by the gcd of the coefficients and round the bound up (integer solutions only),
+/// drop trivial and duplicate constraints
+fn simplify(constraints: Vec<(Vec<i64>, i64)>) -> Vec<(Vec<i64>, i64)> {
+    let mut result: HashMap<Vec<i64>, i64> = HashMap::new();
+    for (mut a, mut b) in constraints {
+        let g = a.iter().fold(0, |g, &x| gcd(g, x.abs()));
+        if g == 0 {
+            if b > 0 {
+                return vec![(a, b)];
+            }
+            continue;
+        }
+        a.iter_mut().for_each(|x| *x /= g);
+        b = b.div_euclid(g) + i64::from(b.rem_euclid(g) != 0);
+        let bound = result.entry(a).or_insert(b);
+        *bound = (*bound).max(b);
+    }
+    result.into_iter().collect()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::example;
+
+    use super::{check_target, fourier_motzkin, parse_target, Verdict};
+
+    #[test]
+    fn test_state_equation() {
+        // t1: b1 -> a1, t2: b1 -> a2
+        let net = example("ba2022/P005.pnawir");
+
+        let target = parse_target("a1 >= 1 & a2 >= 1").unwrap();
+        assert_eq!(check_target(&net, &target), Ok(Verdict::Unreachable));
+        let target = parse_target("a1 = 1 & b1 = 0").unwrap();
+        assert_eq!(check_target(&net, &target), Ok(Verdict::MaybeReachable));
+        let target = parse_target("b1 > 1").unwrap();
+        assert_eq!(check_target(&net, &target), Ok(Verdict::Unreachable));
+        assert!(parse_target("enabled(t1)").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let big = 1i64 << 40;
+        let constraints = vec![(vec![big, big + 1], 1), (vec![-(big + 1), -big], 1)];
+        assert_eq!(fourier_motzkin(constraints, 2), Verdict::Unknown);
+    }
+}
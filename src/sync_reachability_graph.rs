@@ -1,2 +1,12 @@
+pub mod bdd;
 pub mod build_graph;
+pub mod compact_marking;
+pub mod flat_graph;
 pub mod graph;
+pub mod options;
+pub mod sharded;
+pub mod state_space;
+pub mod stubborn;
+pub mod symbolic;
+pub mod symmetry;
+pub mod worklist;
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+/// Node of a Bdd, 0 is false and 1 is true
+pub type BddRef = u32;
+pub type Var = u32;
+
+pub const FALSE: BddRef = 0;
+pub const TRUE: BddRef = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    And,
+    Or,
+}
+
+/// Reduced ordered binary decision diagrams sharing one node table
+/// smaller variables are closer to the root, equal functions have the same BddRef
+#[derive(Debug)]
+pub struct Bdd {
+    /// (var, low, high), the terminals use Var::MAX
+    nodes: Vec<(Var, BddRef, BddRef)>,
+    unique: HashMap<(Var, BddRef, BddRef), BddRef>,
+    apply_cache: HashMap<(Op, BddRef, BddRef), BddRef>,
+    not_cache: HashMap<BddRef, BddRef>,
+}
+
+impl Default for Bdd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bdd {
+    pub fn new() -> Self {
+        Bdd {
+            nodes: vec![(Var::MAX, FALSE, FALSE), (Var::MAX, TRUE, TRUE)],
+            unique: HashMap::new(),
+            apply_cache: HashMap::new(),
+            not_cache: HashMap::new(),
+        }
+    }
+
+    /// number of nodes including the terminals
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn var_of(&self, f: BddRef) -> Var {
+        self.nodes[f as usize].0
+    }
+
+    pub fn low(&self, f: BddRef) -> BddRef {
+        self.nodes[f as usize].1
+    }
+
+    pub fn high(&self, f: BddRef) -> BddRef {
+        self.nodes[f as usize].2
+    }
+
+    pub fn mk(&mut self, var: Var, low: BddRef, high: BddRef) -> BddRef {
+        if low == high {
+            return low;
+        }
+        if let Some(&x) = self.unique.get(&(var, low, high)) {
+            return x;
+        }
+        let id = self.nodes.len() as BddRef;
+        self.nodes.push((var, low, high));
+        self.unique.insert((var, low, high), id);
+        id
+    }
+
+    pub fn var(&mut self, var: Var) -> BddRef {
+        self.mk(var, FALSE, TRUE)
+    }
+
+    pub fn nvar(&mut self, var: Var) -> BddRef {
+        self.mk(var, TRUE, FALSE)
+    }
+
+    pub fn not(&mut self, f: BddRef) -> BddRef {
+        match f {
+            FALSE => TRUE,
+            TRUE => FALSE,
+            _ => {
+                if let Some(&x) = self.not_cache.get(&f) {
+                    return x;
+                }
+                let (var, low, high) = self.nodes[f as usize];
+                let (low, high) = (self.not(low), self.not(high));
+                let result = self.mk(var, low, high);
+                self.not_cache.insert(f, result);
+                result
+            }
+        }
+    }
+
+    pub fn and(&mut self, f: BddRef, g: BddRef) -> BddRef {
+        self.apply(Op::And, f, g)
+    }
+
+    pub fn or(&mut self, f: BddRef, g: BddRef) -> BddRef {
+        self.apply(Op::Or, f, g)
+    }
+
+    /// f <-> g
+    pub fn equiv(&mut self, f: BddRef, g: BddRef) -> BddRef {
+        let (nf, ng) = (self.not(f), self.not(g));
+        let both = self.and(f, g);
+        let none = self.and(nf, ng);
+        self.or(both, none)
+    }
+
+    fn apply(&mut self, op: Op, f: BddRef, g: BddRef) -> BddRef {
+        match (op, f, g) {
+            (Op::And, FALSE, _) | (Op::And, _, FALSE) => return FALSE,
+            (Op::And, TRUE, x) | (Op::And, x, TRUE) => return x,
+            (Op::Or, TRUE, _) | (Op::Or, _, TRUE) => return TRUE,
+            (Op::Or, FALSE, x) | (Op::Or, x, FALSE) => return x,
+            _ if f == g => return f,
+            _ => {}
+        }
+        // both operations are commutative
+        let key = (op, f.min(g), f.max(g));
+        if let Some(&x) = self.apply_cache.get(&key) {
+            return x;
+        }
+        let (vf, vg) = (self.var_of(f), self.var_of(g));
+        let var = vf.min(vg);
+        let (f0, f1) = if vf == var {
+            (self.low(f), self.high(f))
+        } else {
+            (f, f)
+        };
+        let (g0, g1) = if vg == var {
+            (self.low(g), self.high(g))
+        } else {
+            (g, g)
+        };
+        let low = self.apply(op, f0, g0);
+        let high = self.apply(op, f1, g1);
+        let result = self.mk(var, low, high);
+        self.apply_cache.insert(key, result);
+        result
+    }
+
+    /// existential quantification of all variables matching quantified
+    pub fn exists(&mut self, f: BddRef, quantified: &dyn Fn(Var) -> bool) -> BddRef {
+        let mut cache = HashMap::new();
+        self.exists_rec(f, quantified, &mut cache)
+    }
+
+    fn exists_rec(
+        &mut self,
+        f: BddRef,
+        quantified: &dyn Fn(Var) -> bool,
+        cache: &mut HashMap<BddRef, BddRef>,
+    ) -> BddRef {
+        if f == FALSE || f == TRUE {
+            return f;
+        }
+        if let Some(&x) = cache.get(&f) {
+            return x;
+        }
+        let (var, low, high) = self.nodes[f as usize];
+        let low = self.exists_rec(low, quantified, cache);
+        let high = self.exists_rec(high, quantified, cache);
+        let result = if quantified(var) {
+            self.or(low, high)
+        } else {
+            self.mk(var, low, high)
+        };
+        cache.insert(f, result);
+        result
+    }
+
+    /// replace every variable by rename(var), rename has to keep the variable order
+    pub fn rename(&mut self, f: BddRef, rename: &dyn Fn(Var) -> Var) -> BddRef {
+        let mut cache = HashMap::new();
+        self.rename_rec(f, rename, &mut cache)
+    }
+
+    fn rename_rec(
+        &mut self,
+        f: BddRef,
+        rename: &dyn Fn(Var) -> Var,
+        cache: &mut HashMap<BddRef, BddRef>,
+    ) -> BddRef {
+        if f == FALSE || f == TRUE {
+            return f;
+        }
+        if let Some(&x) = cache.get(&f) {
+            return x;
+        }
+        let (var, low, high) = self.nodes[f as usize];
+        let low = self.rename_rec(low, rename, cache);
+        let high = self.rename_rec(high, rename, cache);
+        let result = self.mk(rename(var), low, high);
+        cache.insert(f, result);
+        result
+    }
+
+    /// number of satisfying assignments over the variables 0..vars
+    pub fn sat_count(&self, f: BddRef, vars: Var) -> u128 {
+        let mut cache = HashMap::new();
+        let level = |g: BddRef| self.var_of(g).min(vars);
+        let count = self.sat_count_rec(f, vars, &mut cache);
+        count << level(f)
+    }
+
+    /// assignments of the variables from var_of(f) to vars
+    fn sat_count_rec(&self, f: BddRef, vars: Var, cache: &mut HashMap<BddRef, u128>) -> u128 {
+        match f {
+            FALSE => return 0,
+            TRUE => return 1,
+            _ => {}
+        }
+        if let Some(&x) = cache.get(&f) {
+            return x;
+        }
+        let (var, low, high) = self.nodes[f as usize];
+        let level = |g: BddRef| self.var_of(g).min(vars);
+        let low_count = self.sat_count_rec(low, vars, cache) << (level(low) - var - 1);
+        let high_count = self.sat_count_rec(high, vars, cache) << (level(high) - var - 1);
+        let result = low_count + high_count;
+        cache.insert(f, result);
+        result
+    }
+
+    /// all satisfying assignments over the variables in vars, true variables of each
+    pub fn assignments(&self, f: BddRef, vars: &[Var]) -> Vec<Vec<Var>> {
+        let mut result = vec![];
+        self.assignments_rec(f, vars, &mut vec![], &mut result);
+        result
+    }
+
+    fn assignments_rec(
+        &self,
+        f: BddRef,
+        vars: &[Var],
+        current: &mut Vec<Var>,
+        result: &mut Vec<Vec<Var>>,
+    ) {
+        if f == FALSE {
+            return;
+        }
+        let Some((&var, rest)) = vars.split_first() else {
+            result.push(current.clone());
+            return;
+        };
+        let (low, high) = if self.var_of(f) == var {
+            (self.low(f), self.high(f))
+        } else {
+            (f, f)
+        };
+        self.assignments_rec(low, rest, current, result);
+        current.push(var);
+        self.assignments_rec(high, rest, current, result);
+        current.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bdd, FALSE, TRUE};
+
+    #[test]
+    fn test_bdd() {
+        let mut bdd = Bdd::new();
+        let (a, b, c) = (bdd.var(0), bdd.var(1), bdd.var(2));
+        let ab = bdd.and(a, b);
+        let f = bdd.or(ab, c);
+        assert_eq!(bdd.sat_count(f, 3), 5);
+
+        // canonical, the same function gives the same node
+        let ba = bdd.and(b, a);
+        let g = bdd.or(c, ba);
+        assert_eq!(f, g);
+        let nf = bdd.not(f);
+        assert_eq!(bdd.and(f, nf), FALSE);
+        assert_eq!(bdd.or(f, nf), TRUE);
+
+        // exists b. a & b = a
+        let e = bdd.exists(ab, &|v| v == 1);
+        assert_eq!(e, a);
+        // rename 1 -> 3
+        let r = bdd.rename(b, &|v| if v == 1 { 3 } else { v });
+        assert_eq!(bdd.var_of(r), 3);
+        assert_eq!(
+            bdd.assignments(ab, &[0, 1, 2]),
+            vec![vec![0, 1], vec![0, 1, 2]]
+        );
+    }
+}
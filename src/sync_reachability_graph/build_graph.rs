@@ -1,202 +1,499 @@
 use std::{
     collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
     vec,
 };
 
+use log::{debug, info, trace};
+
 use crate::modular_net::{ModularPetrinet, ModuleId, PetrinetModul, TransitionId};
 
-use super::graph::{
-    Graph, GraphEdge, GraphMarking, Id, Marking, MarkingId, Segment, SyncEdge, SyncMarking,
+use super::{
+    compact_marking::{CompactMarking, MarkingEncoding},
+    graph::{
+        Graph, GraphEdge, GraphMarking, Marking, MarkingId, Segment, SegmentId, SyncEdge,
+        SyncMarking,
+    },
+    options::{ExploreOptions, ExploreStats, Limit},
+    sharded::ShardedMap,
+    stubborn::stubborn_set,
+    symmetry::{symmetric_modules, SegmentKeys},
+    worklist::Worklist,
 };
 
+/// enabled extern transition: (t_id, marking_id, firable in that marking)
+type ExternFirable = (TransitionId, MarkingId, Vec<TransitionId>);
+
+/// extern transition leaving a segment: (t_id, [(pre marking, post marking)])
+type SegmentEdge = (TransitionId, Vec<(MarkingId, MarkingId)>);
+
+/// (module, sorted markings) -> (segment id, stored ids of the sorted markings)
+type SegmentIndex = ShardedMap<(ModuleId, Vec<CompactMarking>), (SegmentId, Arc<[MarkingId]>)>;
+
 pub fn build_sync_reachability_graph(net: &ModularPetrinet) -> Graph {
+    build_sync_reachability_graph_with(net, &ExploreOptions::default())
+}
+
+/// Explore until the whole graph is built or a limit is reached,
+/// a partial graph has incomplete set, its unexplored sync nodes have no edges
+pub fn build_sync_reachability_graph_with(
+    net: &ModularPetrinet,
+    options: &ExploreOptions,
+) -> Graph {
     // dbg!(&net);
+    let start = Instant::now();
+    let report = |graph: &Graph, frontier: usize| {
+        if let Some(observer) = &options.observer {
+            observer.progress(&ExploreStats::new(graph, frontier, start.elapsed()));
+        }
+    };
     let net_count = net.modules.len();
+    let threads = options
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+        .max(1);
+    debug!("exploring {} modules with {} threads", net_count, threads);
 
     // create empty graph
     let mut graph = Graph {
         sync_graph: vec![],
         segment_storage: vec![(vec![], 0); net_count],
+        incomplete: None,
+        marking_pool: vec![],
+        symmetry: if options.symmetry {
+            symmetric_modules(net)
+        } else {
+            vec![]
+        },
     };
-
-    // get initial firable_list
-    let mut firable_list = vec![vec![]; net_count];
-    for id in 0..net_count {
-        let firable = initial_firable(&net.modules[id], &net.markings[id]);
-        firable_list[id] = firable;
+    if !graph.symmetry.is_empty() {
+        debug!("symmetric modules: {:?}", graph.symmetry);
     }
-
-    // explore all initial segments
-    let mut to_explore = vec![];
-    let mut enabled_e_t = vec![vec![]; net_count];
-    for id in 0..net_count {
-        let seg_id = graph.segment_storage[id].0.len();
-        let (new_segment, e_t, max_p_id) = explore_segment(
-            &net.modules[id],
-            &vec![net.markings[id].clone()],
-            &vec![firable_list[id].clone()],
-            seg_id as Id,
+    let shared = Shared::new(net, options, &graph.symmetry, threads);
+
+    // explore all initial segments, the modules are independent
+    let initial_segments = parallel_map(&net.modules, threads, |module| {
+        let marking = &net.markings[module.id as usize];
+        let firable = initial_firable(module, marking);
+        let (segment, e_t, _, limit) = explore_segment(
+            module,
+            std::slice::from_ref(marking),
+            &[firable],
             net.intern_transition_start,
-            graph.segment_storage[id].1,
+            shared.encodings[module.id as usize],
+            options,
         );
-
-        graph.segment_storage[id].1 = max_p_id;
-        graph.segment_storage[id].0.push((new_segment, vec![]));
-        enabled_e_t[id] = e_t;
+        let (_, e_t, _, segment) = shared.store_segment(module.id, segment, e_t);
+        (
+            segment.expect("the first segment of a module is new"),
+            e_t,
+            limit,
+        )
+    });
+    let mut to_explore = Worklist::new(options.strategy);
+    let mut enabled_e_t = vec![];
+    for (id, (segment, e_t, limit)) in initial_segments.into_iter().enumerate() {
+        graph.incomplete = graph.incomplete.or(limit);
+        let count = segment.markings.len() as MarkingId;
+        graph.segment_storage[id] = (vec![(segment, vec![])], count);
+        enabled_e_t.push(e_t);
     }
 
     // seed to explore with first marking
+    let initial = vec![0; net_count];
+    let (id, _) = shared
+        .sync_node(&initial)
+        .expect("the initial sync node is always kept");
     graph.sync_graph.push(SyncMarking {
-        segment_ids: vec![0; net_count],
+        segment_ids: initial,
         edges: vec![],
     });
-    to_explore.push((0, enabled_e_t));
-
-    // sync_id, enabled_e_t
-    while let Some(now_exploring) = to_explore.pop() {
-        // Module[*(t_id, ma_id, firable*[t_id])]
-        let enabled_t_info = now_exploring.1;
-        let c_sync_marking = &graph.sync_graph[now_exploring.0].clone();
+    to_explore.push((id, enabled_e_t));
+
+    // the frontier (a batch of the strategy) is explored in parallel, the workers store and
+    // dedup their segments and sync nodes themselves, the graph is only read meanwhile and the
+    // results are placed by their claimed ids afterwards
+    while !to_explore.is_empty() {
+        report(&graph, to_explore.len());
+        if let Some(limit) = options.interrupted() {
+            graph.incomplete = graph.incomplete.or(Some(limit));
+            break;
+        }
+        // sync_id, Module[*(t_id, ma_id, firable*[t_id])]
+        let frontier: Vec<(usize, Vec<Vec<ExternFirable>>)> = to_explore.pop_batch(threads);
         // find all enabled extern transitions
         // all enabled found transitions, indexes enabled_e_t transitions for every module
-        let indexes = find_all_e_t(&enabled_t_info, &net.extern_t_overview);
-        let mut edges = vec![];
-        // for every global enabled transition
-        for (e_t_id, touched_modules) in &indexes {
-            let mut new_sync_marking = SyncMarking {
-                segment_ids: vec![0; net_count],
-                edges: vec![],
+        let indexes = frontier
+            .iter()
+            .map(|(_, enabled_t_info)| find_all_e_t(enabled_t_info, &net.extern_t_overview))
+            .collect::<Vec<_>>();
+
+        // fire every global enabled transition of every sync node of the frontier
+        let jobs = indexes
+            .iter()
+            .enumerate()
+            .flat_map(|(f_idx, indexes)| {
+                indexes
+                    .iter()
+                    .map(move |(e_t_id, touched)| (f_idx, *e_t_id, touched))
+            })
+            .collect::<Vec<_>>();
+        let fired = parallel_map(&jobs, threads, |&(f_idx, e_t_id, touched)| {
+            let (sync_id, enabled_t_info) = &frontier[f_idx];
+            shared.fire(&graph, *sync_id, enabled_t_info, e_t_id, touched)
+        });
+
+        // the ids claimed in this batch follow the stored ones without gaps
+        let mut edges = vec![vec![]; frontier.len()];
+        let mut new_segments = vec![vec![]; net_count];
+        let mut new_nodes = vec![];
+        for (&(f_idx, _, _), fired) in jobs.iter().zip(fired) {
+            graph.incomplete = graph.incomplete.or(fired.limit);
+            for (m_id, segment) in fired.segments {
+                new_segments[m_id as usize].push(segment);
+            }
+            new_nodes.extend(fired.node);
+            let Some((edge, segment_edges)) = fired.edges else {
+                // without the sync edge the segment edges would lead nowhere
+                graph.incomplete = graph.incomplete.or(Some(Limit::SyncNodes));
+                continue;
             };
+            let source = &graph.sync_graph[frontier[f_idx].0];
+            for (m_id, segment_edge) in segment_edges {
+                let seg_id = source.segment_ids[m_id as usize];
+                graph.segment_storage[m_id as usize].0[seg_id as usize]
+                    .1
+                    .push(segment_edge);
+            }
+            edges[f_idx].push(edge);
+        }
+        for (m_id, mut segments) in new_segments.into_iter().enumerate() {
+            segments.sort_unstable_by_key(|s: &Segment| s.id);
+            let storage = &mut graph.segment_storage[m_id];
+            for segment in segments {
+                assert_eq!(segment.id as usize, storage.0.len());
+                storage.1 += segment.markings.len() as MarkingId;
+                storage.0.push((segment, vec![]));
+            }
+        }
+        for ((sync_id, _), edges) in frontier.iter().zip(edges) {
+            graph.sync_graph[*sync_id].edges = edges;
+        }
+        new_nodes.sort_unstable_by_key(|n| n.0);
+        for (id, node, e_t) in new_nodes {
+            assert_eq!(id, graph.sync_graph.len());
+            graph.sync_graph.push(node);
+            to_explore.push((id, e_t));
+        }
 
-            // for every module
-            let mut e_t_o2 = vec![];
-            for m_id in 0..net_count {
-                // in the modules where this transitions exist
-                if touched_modules.contains(&(m_id as u16)) {
-                    let mut seg_e_e = (*e_t_id, vec![]);
-                    // fire transition
-                    let mut pre_fire_marking = vec![];
-                    let mut start_markings = vec![];
-                    let mut start_firable = vec![];
-                    let t_info = &enabled_t_info[m_id];
-                    for t in t_info {
-                        // only for real real extern_t // all t saved only fire current
-                        if t.0 == *e_t_id {
-                            let c_segment = &graph.segment_storage[m_id].0
-                                [c_sync_marking.segment_ids[m_id] as usize]
-                                .0;
-
-                            let marking = &c_segment.markings
-                                [(t.1 - c_segment.marking_offset) as usize]
-                                .marking;
-                            assert_eq!(
-                                c_segment.markings[(t.1 - c_segment.marking_offset) as usize].id,
-                                t.1
-                            );
-                            pre_fire_marking.push(
-                                &c_segment.markings[(t.1 - c_segment.marking_offset) as usize].id,
-                            );
-                            let x = fire(&net.modules[m_id], marking, &t.2, *e_t_id);
-                            start_markings.push(x.0);
-                            start_firable.push(x.1);
-                        }
-                    }
+        // graph.print(net);
+    }
+    graph.marking_pool = shared.pools();
+    report(&graph, to_explore.len());
+
+    let stats = ExploreStats::new(&graph, to_explore.len(), start.elapsed());
+    info!(
+        "sync graph: {} sync nodes, {} segments, {} markings in {:.3?}",
+        stats.sync_nodes,
+        stats.segments.iter().sum::<usize>(),
+        stats.markings,
+        stats.elapsed
+    );
+    if let Some(limit) = graph.incomplete {
+        info!("sync graph incomplete, limit reached: {:?}", limit);
+    }
+    trace!("{:?}", graph);
+    graph
+}
 
-                    // build new segment
-                    let x = explore_segment(
-                        &net.modules[m_id],
-                        &start_markings,
-                        &start_firable,
-                        graph.segment_storage[m_id].0.len() as u32,
-                        net.intern_transition_start,
-                        graph.segment_storage[m_id].1, // place_start_id
-                    );
-
-                    for (i, _) in start_markings.iter().enumerate() {
-                        seg_e_e.1.push((
-                            *pre_fire_marking[i],
-                            i as u32 + graph.segment_storage[m_id].1,
-                        ))
-                    }
+/// result of a worker firing one extern transition in a sync node
+struct Fired {
+    /// the sync edge and the segment edges of the touched modules, None at the sync node limit
+    edges: Option<(SyncEdge, Vec<(ModuleId, SegmentEdge)>)>,
+    /// the segments first stored by this worker
+    segments: Vec<(ModuleId, Segment)>,
+    /// the sync node first stored by this worker with its enabled extern transitions
+    node: Option<(usize, SyncMarking, Vec<Vec<ExternFirable>>)>,
+    limit: Option<Limit>,
+}
 
-                    // compare with current segments
-                    let seg_id;
-                    if let Some(x) = graph.contains_segment(&x.0, m_id as u16) {
-                        seg_id = x;
-                    } else {
-                        seg_id = graph.segment_storage[m_id].0.len() as u32;
-                        graph.segment_storage[m_id].0.push((x.0, vec![]));
-                        graph.segment_storage[m_id].1 = x.2;
-                        // store segments
-                    }
-                    new_sync_marking.segment_ids[m_id] = seg_id;
+/// State shared by the workers, equal segments and sync nodes get the id of the first one stored
+struct Shared<'a> {
+    net: &'a ModularPetrinet,
+    options: &'a ExploreOptions,
+    encodings: Vec<MarkingEncoding>,
+    segments: SegmentIndex,
+    /// claimed segment ids and marking ids of every module
+    counts: Vec<(AtomicU32, AtomicU32)>,
+    /// every distinct marking of a module, shared by its segments
+    pools: Vec<Mutex<HashSet<CompactMarking>>>,
+    /// sync nodes differing by a permutation of symmetric modules are merged
+    symmetry: Vec<Vec<ModuleId>>,
+    keys: Mutex<SegmentKeys>,
+    /// known sync nodes by their (canonical) segments
+    sync_ids: ShardedMap<Vec<u32>, usize>,
+    sync_count: AtomicUsize,
+}
+
+impl<'a> Shared<'a> {
+    fn new(
+        net: &'a ModularPetrinet,
+        options: &'a ExploreOptions,
+        symmetry: &[Vec<ModuleId>],
+        threads: usize,
+    ) -> Self {
+        let net_count = net.modules.len();
+        Shared {
+            net,
+            options,
+            encodings: net
+                .modules
+                .iter()
+                .map(|m| MarkingEncoding::for_module(net, m.id))
+                .collect(),
+            segments: ShardedMap::new(threads * 4),
+            counts: (0..net_count).map(|_| Default::default()).collect(),
+            pools: (0..net_count).map(|_| Default::default()).collect(),
+            symmetry: symmetry.to_vec(),
+            keys: Mutex::default(),
+            sync_ids: ShardedMap::new(threads * 4),
+            sync_count: AtomicUsize::new(0),
+        }
+    }
 
-                    // link correct marking to marking
+    /// Fire an extern transition in the touched modules of a sync node, explore and store the
+    /// new segments and the reached sync node
+    fn fire(
+        &self,
+        graph: &Graph,
+        sync_id: usize,
+        enabled_t_info: &[Vec<ExternFirable>],
+        e_t_id: TransitionId,
+        touched: &[ModuleId],
+    ) -> Fired {
+        let net = self.net;
+        let source = &graph.sync_graph[sync_id];
+        // in the modules where this transitions did not exist nothing changes
+        let mut segment_ids = source.segment_ids.clone();
+        let mut e_t_o2 = enabled_t_info.to_vec();
+        let mut segment_edges = vec![];
+        let mut fired = Fired {
+            edges: None,
+            segments: vec![],
+            node: None,
+            limit: None,
+        };
 
-                    e_t_o2.push(x.1);
-                    // save segment edges for e_t
+        // in the modules where this transitions exist
+        for &m_id in touched {
+            let m_id = m_id as usize;
+            let c_segment = &graph.segment_storage[m_id].0[source.segment_ids[m_id] as usize].0;
+            let mut pre_fire_marking = vec![];
+            let mut start_markings = vec![];
+            let mut start_firable = vec![];
+            // only for real real extern_t // all t saved only fire current
+            for t in enabled_t_info[m_id].iter().filter(|t| t.0 == e_t_id) {
+                let graph_marking = &c_segment.markings[(t.1 - c_segment.marking_offset) as usize];
+                assert_eq!(graph_marking.id, t.1);
+                pre_fire_marking.push(t.1);
+                let marking = c_segment.encoding.decode(&graph_marking.marking);
+                let x = fire(&net.modules[m_id], &marking, &t.2, e_t_id);
+                start_markings.push(x.0);
+                start_firable.push(x.1);
+            }
 
-                    graph.segment_storage[m_id].0[c_sync_marking.segment_ids[m_id] as usize]
-                        .1
-                        .push(seg_e_e.clone());
-                }
-                // in the modules where this transitions did not exist
-                else {
-                    // just add the current seg_id
-                    // nothing changes
-                    e_t_o2.push(enabled_t_info[m_id].clone());
-                    new_sync_marking.segment_ids[m_id] = c_sync_marking.segment_ids[m_id];
+            // build new segment
+            let (segment, e_t, start_ids, limit) = explore_segment(
+                &net.modules[m_id],
+                &start_markings,
+                &start_firable,
+                net.intern_transition_start,
+                self.encodings[m_id],
+                self.options,
+            );
+            fired.limit = fired.limit.or(limit);
+
+            // compare with the stored segments
+            let (seg_id, e_t, id_map, new) = self.store_segment(m_id as ModuleId, segment, e_t);
+            fired.segments.extend(new.map(|s| (m_id as ModuleId, s)));
+            segment_ids[m_id] = seg_id;
+            e_t_o2[m_id] = e_t;
+
+            // save segment edges for e_t, link pre marking to the new start marking
+            let links = pre_fire_marking
+                .iter()
+                .zip(start_ids)
+                .map(|(&pre, post)| (pre, id_map[post as usize]))
+                .collect();
+            segment_edges.push((m_id as ModuleId, (e_t_id, links)));
+        }
+
+        // links segments (edges), existing sync nodes are reused
+        if let Some((target, new)) = self.sync_node(&segment_ids) {
+            if new {
+                let node = SyncMarking {
+                    segment_ids,
+                    edges: vec![],
+                };
+                fired.node = Some((target, node, e_t_o2));
+            }
+            fired.edges = Some((SyncEdge::new(e_t_id, target as u32), segment_edges));
+        }
+        fired
+    }
+
+    /// Store a segment with marking ids starting at 0, if an equal segment exists it is reused
+    /// returns the segment id, the extern firable with the stored ids, the mapping of the ids
+    /// and the segment with its stored ids if this call stored it
+    fn store_segment(
+        &self,
+        m_id: ModuleId,
+        mut segment: Segment,
+        mut e_t: Vec<ExternFirable>,
+    ) -> (
+        SegmentId,
+        Vec<ExternFirable>,
+        Vec<MarkingId>,
+        Option<Segment>,
+    ) {
+        // share equal markings of other segments, the key below holds the shared ones
+        {
+            let mut pool = self.pools[m_id as usize].lock().unwrap();
+            for m in segment.markings.iter_mut() {
+                match pool.get(&m.marking) {
+                    Some(x) => m.marking = x.clone(),
+                    None => {
+                        pool.insert(m.marking.clone());
+                    }
                 }
             }
+        }
+
+        // equal segments have the same markings in sorted order
+        let len = segment.markings.len();
+        let mut order = (0..len).collect::<Vec<_>>();
+        order.sort_unstable_by(|&a, &b| {
+            segment.markings[a]
+                .marking
+                .cmp(&segment.markings[b].marking)
+        });
+        let markings = order
+            .iter()
+            .map(|&i| segment.markings[i].marking.clone())
+            .collect::<Vec<_>>();
+        let counts = &self.counts[m_id as usize];
+        let claim = || {
+            // shift all ids behind the claimed marking count
+            let seg_id = counts.0.fetch_add(1, Ordering::Relaxed);
+            let offset = counts.1.fetch_add(len as MarkingId, Ordering::Relaxed);
+            let ids = order.iter().map(|&i| offset + i as MarkingId).collect();
+            Some((seg_id, ids))
+        };
+        let ((seg_id, ids), new) = self
+            .segments
+            .get_or_insert_with((m_id, markings.clone()), claim)
+            .expect("segments are always stored");
+        if !self.symmetry.is_empty() {
+            self.keys.lock().unwrap().insert(m_id, seg_id, markings);
+        }
 
-            // same sync_node
-            if new_sync_marking == *c_sync_marking {
-                // links segments (edges)
-                edges.push(SyncEdge {
-                    transition_id: *e_t_id,
-                    sync_marking_id: now_exploring.0 as u32,
-                });
-                // recreate seg -> seg edges
-
-                for m_id in 0..net_count {
-                    let old = graph.segment_storage[m_id].0
-                        [c_sync_marking.segment_ids[m_id] as usize]
-                        .1
-                        .pop()
-                        .unwrap();
-
-                    graph.segment_storage[m_id].0[c_sync_marking.segment_ids[m_id] as usize]
-                        .1
-                        .push((*e_t_id, vec![]));
-
-                    // TODO search for marking_id
+        // map to the equal markings of the stored segment
+        let mut id_map = vec![0; len];
+        for (&i, &id) in order.iter().zip(ids.iter()) {
+            id_map[i] = id;
+        }
+        let new = new.then(|| {
+            let offset = id_map[0];
+            segment.id = seg_id;
+            segment.marking_offset = offset;
+            for m in segment.markings.iter_mut() {
+                m.id += offset;
+                for edge in m.edges.iter_mut() {
+                    edge.graph_marking_id += offset;
                 }
-            } else {
-                // links segments (edges)
-                edges.push(SyncEdge {
-                    transition_id: *e_t_id,
-                    sync_marking_id: (graph.sync_graph.len() as u32),
-                });
-                to_explore.push((graph.sync_graph.len(), e_t_o2));
-                let sync_g = &mut graph.sync_graph;
-                sync_g.push(new_sync_marking);
             }
+            segment
+        });
+        for t in e_t.iter_mut() {
+            t.1 = id_map[t.1 as usize];
         }
-        let sync_g = &mut graph.sync_graph;
-        sync_g[now_exploring.0].edges = edges;
+        (seg_id, e_t, id_map, new)
+    }
 
-        // graph.print(net);
+    /// id of the sync node with these segments and whether this call claimed it,
+    /// None if it is new and max_sync_nodes is reached, the initial node is always kept
+    fn sync_node(&self, segment_ids: &[SegmentId]) -> Option<(usize, bool)> {
+        let key = if self.symmetry.is_empty() {
+            segment_ids.to_vec()
+        } else {
+            let keys = self.keys.lock().unwrap();
+            keys.canonical(&self.symmetry, segment_ids)
+        };
+        let max = self.options.max_sync_nodes.map(|max| max.max(1));
+        self.sync_ids.get_or_insert_with(key, || {
+            self.sync_count
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                    max.is_none_or(|max| n < max).then_some(n + 1)
+                })
+                .ok()
+        })
     }
 
-    dbg!(&graph);
-    graph
+    /// the shared markings of every module
+    fn pools(self) -> Vec<HashSet<CompactMarking>> {
+        self.pools
+            .into_iter()
+            .map(|p| p.into_inner().unwrap())
+            .collect()
+    }
+}
+
+/// map in parallel on up to threads workers, keeps the order of the items
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = threads.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|s| {
+        let workers = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut local = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            break;
+                        }
+                        local.push((i, f(&items[i])));
+                    }
+                    local
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|r| r.0);
+    results.into_iter().map(|r| r.1).collect()
 }
 
 // need to know where extern transitions
 /// return vector index where all saved
 fn find_all_e_t(
-    ex_t: &Vec<Vec<(TransitionId, MarkingId, Vec<TransitionId>)>>,
-    extern_t_overview: &Vec<Vec<ModuleId>>,
+    ex_t: &[Vec<ExternFirable>],
+    extern_t_overview: &[Vec<ModuleId>],
 ) -> Vec<(TransitionId, Vec<u16>)> {
     let mut found_t = HashMap::<u32, HashSet<u16>>::new();
 
@@ -232,6 +529,8 @@ fn find_all_e_t(
             result.push((t.0, res));
         }
     }
+    // same order on every run
+    result.sort_unstable_by_key(|t| t.0);
 
     result
 }
@@ -258,73 +557,87 @@ fn initial_firable(module: &PetrinetModul, marking: &Marking) -> Vec<TransitionI
     firable
 }
 
+/// Explore all markings reachable by intern transitions from the start markings
+/// marking ids start at 0, they are shifted when the segment is stored
+/// returns the segment, the enabled extern transitions, the ids of the start markings
+/// and the limit if the segment was cut off
 fn explore_segment(
     module: &PetrinetModul,
-    marking: &Vec<Marking>,
-    firable: &Vec<Vec<TransitionId>>,
-    seg_id: Id,
+    marking: &[Marking],
+    firable: &[Vec<TransitionId>],
     intern_start: TransitionId,
-    start_marking_id: MarkingId,
-) -> (
-    Segment,
-    Vec<(TransitionId, MarkingId, Vec<TransitionId>)>,
-    MarkingId,
-) {
+    encoding: MarkingEncoding,
+    options: &ExploreOptions,
+) -> (Segment, Vec<ExternFirable>, Vec<MarkingId>, Option<Limit>) {
     assert_eq!(marking.len(), firable.len());
     // collect all extern firable
     // dont fire extern t in local segment
     // t_id, marking_id, Firable
     let mut extern_firable = vec![];
-    // (marking_id, Firable)
-    let mut to_explore = vec![];
-    for (i, m) in firable.iter().enumerate() {
-        for &t in m {
-            // println!("{},{}", t, intern_start);
-            if t < intern_start {
-                // println!("{}",t);
-                extern_firable.push((t, i as u32 + start_marking_id, m.clone()));
-            }
-        }
-    }
+    // (marking_id, Marking, Firable)
+    let mut to_explore = Worklist::new(options.strategy);
 
     let mut segment = Segment {
-        id: seg_id,
-        marking_offset: start_marking_id,
+        id: 0,
+        marking_offset: 0,
         markings: vec![],
+        encoding,
     };
 
-    for m in marking {
-        let id = start_marking_id + segment.markings.len() as u32;
+    // equal start markings are only added once
+    let mut start_ids = vec![];
+    for (m, f) in marking.iter().zip(firable) {
+        let compact = encoding.encode(m);
+        if let Some(x) = segment.search_equal_compact(&compact) {
+            start_ids.push(x);
+            continue;
+        }
+        let id = segment.markings.len() as u32;
+        for &t in f {
+            if t < intern_start {
+                extern_firable.push((t, id, f.clone()));
+            }
+        }
         let graph_marking = GraphMarking {
             id,
-            marking: m.clone(),
+            marking: compact,
             edges: vec![],
         };
-        to_explore.push((id, firable[segment.markings.len()].clone()));
+        to_explore.push((id, m.clone(), f.clone()));
         segment.markings.push(graph_marking);
+        start_ids.push(id);
     }
 
+    let mut limit = None;
     while let Some(now_exploring) = to_explore.pop() {
-        let m_id = now_exploring.0 - segment.marking_offset;
+        if let Some(l) = options.interrupted() {
+            limit = Some(l);
+            break;
+        }
+        let (m_id, marking, firable) = now_exploring;
         let mut edges = vec![];
-        let marking = &segment.markings[m_id as usize].marking.clone();
-        for &t_id in &now_exploring.1 {
-            if t_id < intern_start {
-                continue;
-            }
-            let (new_marking, mut new_firable) = fire(module, marking, &now_exploring.1, t_id);
+        let stubborn = stubborn_set(module, &marking, &firable, intern_start, options.reduction);
+        for t_id in stubborn {
+            let (new_marking, mut new_firable) = fire(module, &marking, &firable, t_id);
+            let compact = encoding.encode(&new_marking);
             // dbg!(t_id);
             // dbg!(&new_marking);
 
             // check if marking exists
             let mark_id;
-            if let Some(x) = segment.search_equal_marking(&new_marking) {
+            if let Some(x) = segment.search_equal_compact(&compact) {
                 mark_id = x;
+            } else if options
+                .max_segment_markings
+                .is_some_and(|max| segment.markings.len() >= max)
+            {
+                limit = Some(Limit::SegmentMarkings);
+                continue;
             } else {
-                mark_id = segment.marking_offset + segment.markings.len() as u32;
+                mark_id = segment.markings.len() as u32;
                 let new_graph_marking = GraphMarking {
                     id: mark_id,
-                    marking: new_marking,
+                    marking: compact,
                     edges: vec![],
                 };
 
@@ -337,7 +650,7 @@ fn explore_segment(
                     extern_firable.push((t, mark_id, new_firable.clone()));
                 }
 
-                to_explore.push((mark_id, new_firable.clone()));
+                to_explore.push((mark_id, new_marking, new_firable.clone()));
                 segment.markings.push(new_graph_marking);
             }
 
@@ -347,20 +660,19 @@ fn explore_segment(
         graph_marking.edges = edges;
     }
 
-    let start_marking_id = start_marking_id + segment.markings.len() as u32;
     // segment.print(module);
-    (segment, extern_firable, start_marking_id)
+    (segment, extern_firable, start_ids, limit)
 }
 
 fn fire(
     module: &PetrinetModul,
     marking: &Marking,
-    firable: &Vec<TransitionId>,
+    firable: &[TransitionId],
     id: TransitionId,
 ) -> (Marking, Vec<TransitionId>) {
     // dbg!(&marking);
     let mut marking = marking.clone();
-    let mut firable = firable.clone();
+    let mut firable = firable.to_vec();
     let transition = &module.transitions[id as usize];
     // dbg!(&transition);
 
@@ -414,3 +726,249 @@ fn fire(
 
 // damit die Datenstruktur nicht verändert werden muss und
 // immer referenziert werden kann!
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        modular_net::ModuleId,
+        sync_reachability_graph::graph::SyncMarking,
+        testing::{example, net, read},
+    };
+
+    use super::{
+        build_sync_reachability_graph, build_sync_reachability_graph_with, parallel_map,
+        ExploreOptions, Limit,
+    };
+    use crate::sync_reachability_graph::{
+        options::{CancelToken, ExploreObserver, ExploreStats, Strategy},
+        state_space::expand,
+    };
+    use std::{
+        collections::{HashMap, HashSet},
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    #[test]
+    fn test_parallel_map() {
+        let items = (0..100).collect::<Vec<u32>>();
+        let result = parallel_map(&items, 4, |x| x * 2);
+        assert_eq!(result, (0..100).map(|x| x * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_duplicate_segments() {
+        // equal segments are reached from different sync nodes
+        let net = example("ba2022/P006a.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        assert_eq!(graph.sync_graph.len(), 6);
+
+        // every sync node exists only once
+        let mut segment_ids = graph
+            .sync_graph
+            .iter()
+            .map(|s| s.segment_ids.clone())
+            .collect::<Vec<_>>();
+        segment_ids.sort_unstable();
+        segment_ids.dedup();
+        assert_eq!(segment_ids.len(), graph.sync_graph.len());
+
+        // equal markings of different segments are stored once
+        for (m_id, (segments, count)) in graph.segment_storage.iter().enumerate() {
+            let pool = &graph.marking_pool[m_id];
+            let mut markings = segments.iter().flat_map(|s| s.0.markings.iter());
+            assert!(*count as usize >= pool.len());
+            assert!(markings.all(|m| pool.get(&m.marking).unwrap().shares(&m.marking)));
+        }
+        let pooled = graph.marking_pool.iter().map(|p| p.len()).sum::<usize>();
+        let stored = graph.segment_storage.iter().map(|s| s.1 as usize).sum();
+        assert!(pooled < stored);
+    }
+
+    #[test]
+    fn test_limits() {
+        let net = example("ba2022/P006a.pnawir");
+        assert_eq!(build_sync_reachability_graph(&net).incomplete, None);
+
+        let options = ExploreOptions {
+            max_sync_nodes: Some(3),
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+        assert_eq!(graph.sync_graph.len(), 3);
+        assert_eq!(graph.incomplete, Some(Limit::SyncNodes));
+        // no extern edge of a segment without the sync edge it belongs to
+        let mut linked = HashSet::new();
+        for node in &graph.sync_graph {
+            for edge in &node.edges {
+                for &m_id in &net.extern_t_overview[edge.transition_id as usize] {
+                    let seg_id = node.segment_ids[m_id as usize];
+                    linked.insert((m_id, seg_id, edge.transition_id));
+                }
+            }
+        }
+        for (m_id, (segments, _)) in graph.segment_storage.iter().enumerate() {
+            for (segment, extern_edges) in segments {
+                for (t_id, _) in extern_edges {
+                    assert!(linked.contains(&(m_id as ModuleId, segment.id, *t_id)));
+                }
+            }
+        }
+
+        let options = ExploreOptions {
+            max_segment_markings: Some(1),
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+        assert_eq!(graph.incomplete, Some(Limit::SegmentMarkings));
+        assert!(graph
+            .segment_storage
+            .iter()
+            .all(|s| s.0.iter().all(|seg| seg.0.markings.len() == 1)));
+
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let options = ExploreOptions {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+        assert_eq!(graph.sync_graph.len(), 1);
+        assert_eq!(graph.incomplete, Some(Limit::Cancelled));
+
+        // a passed deadline keeps the initial node and only the start markings of its segments
+        let options = ExploreOptions {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+        assert_eq!(graph.incomplete, Some(Limit::Deadline));
+        assert_eq!(graph.sync_graph.len(), 1);
+        assert!(graph.sync_graph[0].edges.is_empty());
+        assert!(graph
+            .segment_storage
+            .iter()
+            .all(|s| s.0.len() == 1 && s.0[0].0.markings.len() == 1));
+        assert_eq!(expand(&net, &graph).states.len(), 1);
+    }
+
+    #[test]
+    fn test_observer() {
+        struct Record(Mutex<Vec<ExploreStats>>);
+        impl ExploreObserver for Record {
+            fn progress(&self, stats: &ExploreStats) {
+                self.0.lock().unwrap().push(stats.clone());
+            }
+        }
+
+        let net = example("ba2022/P006a.pnawir");
+        let record = Arc::new(Record(Mutex::new(vec![])));
+        let options = ExploreOptions {
+            observer: Some(record.clone()),
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+
+        let stats = record.0.lock().unwrap();
+        assert!(stats.len() > 1);
+        assert_eq!(stats[0].sync_nodes, 1);
+        let last = stats.last().unwrap();
+        assert_eq!(last.sync_nodes, graph.sync_graph.len());
+        assert_eq!(last.frontier, 0);
+        assert!(stats.windows(2).all(|w| w[0].markings <= w[1].markings));
+    }
+
+    #[test]
+    fn test_strategies() {
+        let net = example("ba2022/P006a.pnawir");
+        let build = |strategy| {
+            let options = ExploreOptions {
+                strategy,
+                threads: Some(1),
+                ..Default::default()
+            };
+            let graph = build_sync_reachability_graph_with(&net, &options);
+            let nodes = graph
+                .sync_graph
+                .iter()
+                .map(|s| (s.segment_ids.clone(), s.edges.clone()))
+                .collect::<Vec<_>>();
+            (nodes, expand(&net, &graph).states.len())
+        };
+
+        // the numbering is the same on every run with a single worker
+        let bfs = build(Strategy::Bfs);
+        assert_eq!(bfs, build(Strategy::Bfs));
+        assert_eq!(build(Strategy::Random(3)), build(Strategy::Random(3)));
+
+        let dfs = build(Strategy::Dfs);
+        assert_eq!(bfs.0.len(), dfs.0.len());
+        assert_eq!(bfs.1, dfs.1);
+    }
+
+    #[test]
+    fn test_parallel() {
+        // 9 philosophers, thousands of sync nodes
+        let input = read("templates/philosophers.pnawir")
+            .replace("0..5", "0..9")
+            .replace("(i + 4) % 5", "(i + 8) % 9");
+        let net = net(&input);
+
+        // the sync nodes by the markings of their segments with their edges, without the ids
+        let describe = |strategy, threads| {
+            let options = ExploreOptions {
+                strategy,
+                threads: Some(threads),
+                ..Default::default()
+            };
+            let graph = build_sync_reachability_graph_with(&net, &options);
+            assert_eq!(graph.incomplete, None);
+            for (segments, count) in &graph.segment_storage {
+                let markings = segments.iter().map(|s| s.0.markings.len() as u32).sum();
+                assert_eq!(*count, markings);
+            }
+            let key = |node: &SyncMarking| {
+                let markings = |(m_id, &seg_id): (usize, &u32)| {
+                    let segment = &graph.segment_storage[m_id].0[seg_id as usize].0;
+                    let mut markings = segment
+                        .markings
+                        .iter()
+                        .map(|m| m.marking.clone())
+                        .collect::<Vec<_>>();
+                    markings.sort_unstable();
+                    markings
+                };
+                node.segment_ids
+                    .iter()
+                    .enumerate()
+                    .map(markings)
+                    .collect::<Vec<_>>()
+            };
+            let nodes = graph
+                .sync_graph
+                .iter()
+                .map(|node| {
+                    let mut edges = node
+                        .edges
+                        .iter()
+                        .map(|e| {
+                            let target = &graph.sync_graph[e.sync_marking_id as usize];
+                            (e.transition_id, key(target))
+                        })
+                        .collect::<Vec<_>>();
+                    edges.sort_unstable();
+                    (key(node), edges)
+                })
+                .collect::<HashMap<_, _>>();
+            // every sync node is stored once
+            assert_eq!(nodes.len(), graph.sync_graph.len());
+            nodes
+        };
+
+        let sequential = describe(Strategy::Bfs, 1);
+        assert!(sequential.len() > 2000);
+        assert_eq!(describe(Strategy::Bfs, 4), sequential);
+        assert_eq!(describe(Strategy::Dfs, 4), sequential);
+        assert_eq!(describe(Strategy::Random(5), 3), sequential);
+    }
+}
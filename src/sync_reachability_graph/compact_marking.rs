@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use crate::{
+    modular_net::{ModularPetrinet, ModuleId},
+    structural::{
+        flat_net::FlatNet,
+        invariants::{p_invariants, place_bounds},
+    },
+};
+
+use super::graph::{Marking, PlaceCount};
+
+/// Bit-packed layout of the markings of one module, the same number of bits for every place
+/// 1 bit for safe modules, 32 bits (dense) if a place is not structurally bounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkingEncoding {
+    bits: u32,
+    places: u32,
+}
+
+/// Packed place counts, clones share the same words
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CompactMarking(Arc<[u64]>);
+
+impl MarkingEncoding {
+    /// bound is the maximal count of every place, None if unknown
+    pub fn new(places: usize, bound: Option<u64>) -> Self {
+        let bits = match bound {
+            Some(b) if b <= u32::MAX as u64 => (64 - b.leading_zeros()).max(1),
+            _ => 32,
+        };
+        MarkingEncoding {
+            bits,
+            places: places as u32,
+        }
+    }
+
+    /// bounds from the P-invariants of the module, they hold in the composed net as well
+    /// unknown if the invariants can not be computed
+    pub fn for_module(net: &ModularPetrinet, m_id: ModuleId) -> Self {
+        let flat = FlatNet::module(net, m_id);
+        let bounds = place_bounds(&flat, &p_invariants(&flat).unwrap_or_default());
+        let bound = bounds.iter().try_fold(0, |max, b| b.map(|b| max.max(b)));
+        Self::new(flat.places.len(), bound)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn encode(&self, marking: &Marking) -> CompactMarking {
+        let len = (self.places as usize * self.bits as usize).div_ceil(64);
+        let mut words = vec![0u64; len];
+        for pc in &marking.place_counts {
+            assert!(
+                self.bits == 32 || pc.count >> self.bits == 0,
+                "count exceeds the bound of the encoding"
+            );
+            let bit = pc.place_id as usize * self.bits as usize;
+            let (word, shift) = (bit / 64, bit % 64);
+            words[word] |= (pc.count as u64) << shift;
+            // the count continues in the next word
+            if shift + self.bits as usize > 64 {
+                words[word + 1] |= (pc.count as u64) >> (64 - shift);
+            }
+        }
+        CompactMarking(words.into())
+    }
+
+    pub fn decode(&self, compact: &CompactMarking) -> Marking {
+        let mask = (1u64 << self.bits) - 1;
+        let words = &compact.0;
+        let place_counts = (0..self.places)
+            .filter_map(|p| {
+                let bit = p as usize * self.bits as usize;
+                let (word, shift) = (bit / 64, bit % 64);
+                let mut count = words[word] >> shift;
+                if shift + self.bits as usize > 64 {
+                    count |= words[word + 1] << (64 - shift);
+                }
+                let count = (count & mask) as u32;
+                (count > 0).then(|| PlaceCount::new(p, count))
+            })
+            .collect();
+        Marking { place_counts }
+    }
+}
+
+impl CompactMarking {
+    /// bytes of the packed words
+    pub fn size(&self) -> usize {
+        self.0.len() * 8
+    }
+
+    /// both share the same words
+    pub fn shares(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_reachability_graph::graph::{Marking, PlaceCount},
+        testing::example,
+    };
+
+    use super::MarkingEncoding;
+
+    #[test]
+    fn test_encoding() {
+        // a1 + a2 = 1
+        let net = example("ba2022/P006.pnawir");
+        let encoding = MarkingEncoding::for_module(&net, 0);
+        assert_eq!(encoding.bits(), 1);
+
+        // counts crossing the word boundary
+        let encoding = MarkingEncoding::new(20, Some(100));
+        assert_eq!(encoding.bits(), 7);
+        let marking = Marking {
+            place_counts: vec![
+                PlaceCount::new(0, 3),
+                PlaceCount::new(9, 100),
+                PlaceCount::new(19, 1),
+            ],
+        };
+        let compact = encoding.encode(&marking);
+        assert_eq!(compact.size(), 24);
+        assert_eq!(encoding.decode(&compact), marking);
+
+        let dense = MarkingEncoding::new(3, None);
+        let marking = Marking {
+            place_counts: vec![PlaceCount::new(1, u32::MAX)],
+        };
+        assert_eq!(dense.decode(&dense.encode(&marking)), marking);
+    }
+}
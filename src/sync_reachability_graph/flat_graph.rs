@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+
+use log::info;
+
+use crate::{
+    modular_net::{ModuleId, Weight},
+    structural::flat_net::FlatNet,
+};
+
+use super::{
+    graph::Graph,
+    options::{ExploreOptions, Limit},
+    state_space::StateSpace,
+    worklist::Worklist,
+};
+
+/// Reachability graph of the composed net without modules, the baseline for the sync graph
+#[derive(Debug)]
+pub struct FlatGraph {
+    /// token count of every place of the flat net, the marking 0 is the initial marking
+    pub markings: Vec<Vec<Weight>>,
+    /// (transition index of the flat net, target marking)
+    pub edges: Vec<Vec<(usize, usize)>>,
+    /// the limit which stopped the exploration, None if the graph is complete
+    pub incomplete: Option<Limit>,
+}
+
+impl FlatGraph {
+    pub fn edge_count(&self) -> usize {
+        self.edges.iter().map(|e| e.len()).sum()
+    }
+}
+
+/// Explore every reachable marking of the flat net
+/// only the deadline, the cancel token and the strategy of the options are used
+pub fn build_flat_graph(flat: &FlatNet, options: &ExploreOptions) -> FlatGraph {
+    let mut graph = FlatGraph {
+        markings: vec![flat.initial.clone()],
+        edges: vec![vec![]],
+        incomplete: None,
+    };
+    let mut known = HashMap::from([(flat.initial.clone(), 0)]);
+    let mut worklist = Worklist::new(options.strategy);
+    worklist.push(0);
+
+    while let Some(id) = worklist.pop() {
+        if let Some(limit) = options.interrupted() {
+            graph.incomplete = Some(limit);
+            break;
+        }
+        let marking = graph.markings[id].clone();
+        let mut edges = vec![];
+        for t in 0..flat.transitions.len() {
+            let places = 0..flat.places.len();
+            if places.clone().any(|p| marking[p] < flat.pre[p][t]) {
+                continue;
+            }
+            let next = places
+                .map(|p| marking[p] - flat.pre[p][t] + flat.post[p][t])
+                .collect::<Vec<_>>();
+            let next_id = *known.entry(next.clone()).or_insert_with(|| {
+                graph.markings.push(next);
+                graph.edges.push(vec![]);
+                worklist.push(graph.markings.len() - 1);
+                graph.markings.len() - 1
+            });
+            edges.push((t, next_id));
+        }
+        graph.edges[id] = edges;
+    }
+
+    info!(
+        "flat graph: {} markings, {} edges",
+        graph.markings.len(),
+        graph.edge_count()
+    );
+    graph
+}
+
+/// Result of the coverability search on the flat net
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bound {
+    /// maximal token count of a place in every reachable marking
+    Bounded(Weight),
+    /// a reachable marking strictly covers one of its ancestors, the places which grow
+    Unbounded(Vec<usize>),
+    /// the limit which stopped the search
+    Unknown(Limit),
+}
+
+/// Depth first search which stops at a marking strictly covering a marking on its path,
+/// so it ends on unbounded nets as well (every infinite path has such a pair)
+/// only the deadline and the cancel token of the options are used
+pub fn coverability(flat: &FlatNet, options: &ExploreOptions) -> Bound {
+    let mut markings = vec![flat.initial.clone()];
+    let mut known = HashSet::from([flat.initial.clone()]);
+    // (marking, next transition to fire), the markings form the current path
+    let mut path = vec![(0, 0)];
+
+    while let Some(&mut (id, ref mut t)) = path.last_mut() {
+        if let Some(limit) = options.interrupted() {
+            return Bound::Unknown(limit);
+        }
+        if *t == flat.transitions.len() {
+            path.pop();
+            continue;
+        }
+        let t_idx = *t;
+        *t += 1;
+        let marking = &markings[id];
+        let places = 0..flat.places.len();
+        if places.clone().any(|p| marking[p] < flat.pre[p][t_idx]) {
+            continue;
+        }
+        let next = places
+            .map(|p| marking[p] - flat.pre[p][t_idx] + flat.post[p][t_idx])
+            .collect::<Vec<_>>();
+        if known.contains(&next) {
+            continue;
+        }
+        // next is new and so differs from every marking on the path
+        for &(ancestor, _) in &path {
+            let ancestor = &markings[ancestor];
+            if next.iter().zip(ancestor).all(|(n, a)| n >= a) {
+                let grow = (0..next.len()).filter(|&p| next[p] > ancestor[p]).collect();
+                return Bound::Unbounded(grow);
+            }
+        }
+        known.insert(next.clone());
+        markings.push(next);
+        path.push((markings.len() - 1, 0));
+    }
+
+    let bound = markings.iter().flatten().copied().max().unwrap_or(0);
+    info!(
+        "coverability: {} markings, {}-bounded",
+        markings.len(),
+        bound
+    );
+    Bound::Bounded(bound)
+}
+
+/// markings of the expanded state space as markings of the flat net
+/// states of different sync nodes with the same markings are merged
+pub fn distinct_markings(
+    flat: &FlatNet,
+    graph: &Graph,
+    space: &StateSpace,
+) -> HashSet<Vec<Weight>> {
+    (0..space.states.len())
+        .map(|s| {
+            let mut marking = vec![0; flat.places.len()];
+            for m_id in 0..graph.segment_storage.len() as ModuleId {
+                for pc in space.marking(graph, s, m_id).place_counts {
+                    marking[flat.place_index(m_id, pc.place_id)] = pc.count;
+                }
+            }
+            marking
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use crate::{
+        structural::flat_net::FlatNet,
+        sync_reachability_graph::{
+            build_graph::build_sync_reachability_graph,
+            options::{ExploreOptions, Limit},
+            state_space::expand,
+        },
+        testing::{example, net},
+    };
+
+    use super::{build_flat_graph, coverability, distinct_markings, Bound};
+
+    #[test]
+    fn test_flat_graph() {
+        let inputs = [
+            "ba2022/P001.pnawir",
+            "ba2022/P002.pnawir",
+            "ba2022/P005.pnawir",
+            "ba2022/P006a.pnawir",
+            "symmetry/S001.pnawir",
+        ];
+        for input in inputs {
+            let net = example(input);
+            let flat = FlatNet::new(&net);
+            let flat_graph = build_flat_graph(&flat, &ExploreOptions::default());
+            assert!(flat_graph.incomplete.is_none());
+
+            // the sync graph describes the same markings
+            let graph = build_sync_reachability_graph(&net);
+            let space = expand(&net, &graph);
+            let markings = distinct_markings(&flat, &graph, &space);
+            assert_eq!(markings.len(), flat_graph.markings.len());
+            assert!(flat_graph.markings.iter().all(|m| markings.contains(m)));
+        }
+    }
+
+    #[test]
+    fn test_coverability() {
+        // bounded, the bound of the complete flat graph
+        for input in [
+            "ba2022/P001.pnawir",
+            "ba2022/P005.pnawir",
+            "ba2022/P006a.pnawir",
+        ] {
+            let flat = FlatNet::new(&example(input));
+            let flat_graph = build_flat_graph(&flat, &ExploreOptions::default());
+            let max = flat_graph.markings.iter().flatten().copied().max().unwrap();
+            let bound = coverability(&flat, &ExploreOptions::default());
+            assert_eq!(bound, Bound::Bounded(max), "{}", input);
+        }
+
+        // t puts a token on q every time it fires
+        let net = net("{\n    t: M1.p -> M1.p, M2.q\n}\n\nM1 {\n    p(1)\n}\n\nM2 {\n    q\n}\n");
+        let flat = FlatNet::new(&net);
+        let bound = coverability(&flat, &ExploreOptions::default());
+        assert_eq!(bound, Bound::Unbounded(vec![flat.place_index(1, 0)]));
+
+        let options = ExploreOptions {
+            deadline: Some(Instant::now()),
+            ..ExploreOptions::default()
+        };
+        assert_eq!(
+            coverability(&flat, &options),
+            Bound::Unknown(Limit::Deadline)
+        );
+    }
+}
@@ -1,19 +1,37 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    io::{self, Write},
+};
 
 use crate::modular_net::{ModularPetrinet, ModuleId, PetrinetModul, PlaceId, TransitionId, Weight};
 
+use super::{
+    compact_marking::{CompactMarking, MarkingEncoding},
+    options::Limit,
+};
+
 pub type Id = u32;
 type Count = u32;
 pub type MarkingId = Id;
 pub type SegmentId = Id;
+/// extern transitions leaving a segment: (t_id, [(pre marking, post marking)])
+pub type SegmentEdges = Vec<(TransitionId, Vec<(MarkingId, MarkingId)>)>;
 
 #[derive(Debug)]
 pub struct Graph {
     pub sync_graph: Vec<SyncMarking>,
     pub segment_storage: Vec<(
-        Vec<(Segment, Vec<(TransitionId, Vec<(MarkingId, MarkingId)>)>)>,
+        Vec<(Segment, SegmentEdges)>,
         MarkingId, // current marking count overall
     )>,
+    /// the limit which stopped the exploration, None if the graph is complete
+    pub incomplete: Option<Limit>,
+    /// every distinct marking of a module is stored once and shared by the segments
+    pub marking_pool: Vec<HashSet<CompactMarking>>,
+    /// classes of symmetric modules, sync edges lead to a permutation of the target within
+    /// these classes, empty without symmetry reduction
+    pub symmetry: Vec<Vec<ModuleId>>,
 }
 
 impl Graph {
@@ -21,61 +39,66 @@ impl Graph {
         self.sync_graph.iter().position(|sm_a| sm_a == &sm_b)
     }
 
-    pub fn contains_segment(&self, segment: &Segment, m_id: ModuleId) -> Option<SegmentId> {
-        let segs = &self.segment_storage[m_id as usize].0;
-        let segment_sort = segment.sort_marking();
-        for (idx, (seg, _)) in segs.iter().enumerate() {
-            if seg.markings.len() != segment.markings.len() {
-                continue;
-            }
-            let seg_sort = seg.sort_marking();
-            if segment_sort == seg_sort {
-                return Some(idx as u32);
-            }
-        }
+    pub fn graph_marking(
+        &self,
+        m_id: ModuleId,
+        seg_id: SegmentId,
+        marking_id: MarkingId,
+    ) -> &GraphMarking {
+        let segment = &self.segment_storage[m_id as usize].0[seg_id as usize].0;
+        &segment.markings[(marking_id - segment.marking_offset) as usize]
+    }
 
-        None
+    pub fn marking(&self, m_id: ModuleId, seg_id: SegmentId, marking_id: MarkingId) -> Marking {
+        let segment = &self.segment_storage[m_id as usize].0[seg_id as usize].0;
+        segment.marking(marking_id)
     }
 
     pub fn print(&self, net: &ModularPetrinet) {
+        self.write(net, &mut io::stdout().lock())
+            .expect("writing to stdout");
+    }
+
+    pub fn write(&self, net: &ModularPetrinet, out: &mut dyn Write) -> io::Result<()> {
         for (id, node) in self.sync_graph.iter().enumerate() {
-            println!("SyncId: {} | Segments: {:?}", id, node.segment_ids);
+            writeln!(out, "SyncId: {} | Segments: {:?}", id, node.segment_ids)?;
 
             for edge in &node.edges {
                 let mut t_name = "";
                 let mut ni = 0;
-                while t_name == "" {
+                while t_name.is_empty() {
                     t_name = &net.modules[ni].transitions[edge.transition_id as usize].name;
                     ni += 1;
                 }
-                println!("  {} -> {}", t_name, edge.sync_marking_id);
+                writeln!(out, "  {} -> {}", t_name, edge.sync_marking_id)?;
             }
-            println!();
+            writeln!(out)?;
         }
 
-        println!();
-        println!("Segments");
+        writeln!(out)?;
+        writeln!(out, "Segments")?;
         for module in 0..self.segment_storage.len() {
-            println!("  Module: {}", module);
+            writeln!(out, "  Module: {}", module)?;
             for segment in &self.segment_storage[module].0 {
-                segment.0.print(&net.modules[module], 4);
+                segment.0.write(&net.modules[module], 4, out)?;
 
                 let e_ts = &segment.1;
                 for e_t in e_ts {
                     let mut t_name = "";
                     let mut ni = 0;
-                    while t_name == "" {
+                    while t_name.is_empty() {
                         t_name = &net.modules[ni].transitions[e_t.0 as usize].name;
                         ni += 1;
                     }
-                    println!("    {}", t_name);
+                    writeln!(out, "    {}", t_name)?;
                     for t in &e_t.1 {
-                        println!("      {} -> {}", t.0, t.1);
+                        writeln!(out, "      {} -> {}", t.0, t.1)?;
                     }
-                    println!();
+                    writeln!(out)?;
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -117,52 +140,68 @@ pub struct Segment {
     pub id: Id,
     pub marking_offset: Id,
     pub markings: Vec<GraphMarking>,
+    pub encoding: MarkingEncoding,
 }
 
 impl Segment {
     pub fn search_equal_marking(&self, marking: &Marking) -> Option<MarkingId> {
+        self.search_equal_compact(&self.encoding.encode(marking))
+    }
+
+    pub fn search_equal_compact(&self, marking: &CompactMarking) -> Option<MarkingId> {
         for m in &self.markings {
             if m.marking.eq(marking) {
-                return Some(m.id as u32);
+                return Some(m.id);
             }
         }
         None
     }
 
-    fn sort_marking(&self) -> Vec<GraphMarking> {
-        let mut result = self.markings.clone();
-        result.sort_unstable_by(|a, b| a.marking.cmp(&b.marking));
-        result
+    /// decoded marking
+    pub fn marking(&self, marking_id: MarkingId) -> Marking {
+        let m = &self.markings[(marking_id - self.marking_offset) as usize];
+        self.encoding.decode(&m.marking)
     }
 
     pub fn print(&self, module: &PetrinetModul, pre_spacing: usize) {
+        self.write(module, pre_spacing, &mut io::stdout().lock())
+            .expect("writing to stdout");
+    }
+
+    pub fn write(
+        &self,
+        module: &PetrinetModul,
+        pre_spacing: usize,
+        out: &mut dyn Write,
+    ) -> io::Result<()> {
         let space = " ".repeat(pre_spacing);
-        println!("{}SegmentId: {}", space, self.id);
+        writeln!(out, "{}SegmentId: {}", space, self.id)?;
         for m in &self.markings {
-            println!("{}  MarkingId: {}", space, m.id);
-            // println!("PlaceCount");
-            print!("{}    ", space);
-            for p in &m.marking.place_counts {
+            writeln!(out, "{}  MarkingId: {}", space, m.id)?;
+            write!(out, "{}    ", space)?;
+            for p in &self.encoding.decode(&m.marking).place_counts {
                 let p_real = &module.places[p.place_id as usize];
-                print!("{}({}), ", p_real.name, p.count);
+                write!(out, "{}({}), ", p_real.name, p.count)?;
             }
-            println!();
+            writeln!(out)?;
             for edge in &m.edges {
                 let t_real = &module.transitions[edge.transition_id as usize];
-                println!(
+                writeln!(
+                    out,
                     "{}      {} -> {}",
                     space, t_real.name, edge.graph_marking_id
-                );
+                )?;
             }
-            println!();
+            writeln!(out)?;
         }
+        Ok(())
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct GraphMarking {
     pub id: Id,
-    pub marking: Marking,
+    pub marking: CompactMarking,
     pub edges: Vec<GraphEdge>,
 }
 
@@ -187,8 +226,7 @@ impl Marking {
     }
 
     pub fn sort(&mut self) {
-        self.place_counts
-            .sort_by(|a, b| a.place_id.cmp(&b.place_id));
+        self.place_counts.sort_by_key(|a| a.place_id);
     }
 
     pub fn update(&mut self, id: PlaceId, amount: u32, add: bool) -> Weight {
@@ -204,11 +242,11 @@ impl Marking {
                         return 0;
                     }
                 }
-                return self.place_counts[x].count;
+                self.place_counts[x].count
             }
             Err(x) => {
                 self.place_counts.insert(x, PlaceCount::new(id, amount));
-                return amount;
+                amount
             }
         }
     }
@@ -0,0 +1,120 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use super::graph::Graph;
+
+/// Limits of the sync reachability graph exploration
+/// the exploration stops at the first limit reached and the graph is flagged incomplete
+#[derive(Clone, Default)]
+pub struct ExploreOptions {
+    pub max_sync_nodes: Option<usize>,
+    /// segments are cut off at this many markings
+    pub max_segment_markings: Option<usize>,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancelToken>,
+    /// called after every explored frontier and once at the end
+    pub observer: Option<Arc<dyn ExploreObserver>>,
+    /// order of the sync nodes and of the markings within a segment
+    pub strategy: Strategy,
+    /// partial order reduction of the segments
+    pub reduction: Reduction,
+    /// merge sync nodes which differ only by a permutation of identical modules
+    pub symmetry: bool,
+    /// workers exploring and storing the segments, all available cores if None,
+    /// the ids of the graph are only the same on every run with a single worker
+    pub threads: Option<usize>,
+}
+
+/// Stubborn set reduction of the intern transitions within a segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reduction {
+    #[default]
+    None,
+    /// keeps every marking without enabled intern transitions and the deadlocks of the net
+    Deadlocks,
+    /// additionally every extern transition enabled in the full segment stays enabled
+    /// in at least one marking of the reduced segment
+    Interface,
+}
+
+/// Traversal order of the exploration
+/// Bfs explores the whole sync frontier in parallel, the others as many sync nodes as there are
+/// workers in their order; the workers store the segments and sync nodes they reach
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    #[default]
+    Bfs,
+    Dfs,
+    /// random order from a seed, the same seed gives the same graph with a single worker
+    Random(u64),
+}
+
+impl ExploreOptions {
+    /// deadline passed or cancelled
+    pub fn interrupted(&self) -> Option<Limit> {
+        if self.cancel.as_ref().is_some_and(|c| c.is_cancelled()) {
+            return Some(Limit::Cancelled);
+        }
+        if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            return Some(Limit::Deadline);
+        }
+        None
+    }
+}
+
+/// Shared flag to stop a running exploration from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The limit which stopped the exploration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    SyncNodes,
+    SegmentMarkings,
+    Deadline,
+    Cancelled,
+}
+
+/// Counts of the graph during the exploration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExploreStats {
+    pub sync_nodes: usize,
+    /// segments of every module
+    pub segments: Vec<usize>,
+    /// markings of all segments
+    pub markings: usize,
+    /// sync nodes waiting to be explored
+    pub frontier: usize,
+    pub elapsed: Duration,
+}
+
+impl ExploreStats {
+    pub fn new(graph: &Graph, frontier: usize, elapsed: Duration) -> Self {
+        ExploreStats {
+            sync_nodes: graph.sync_graph.len(),
+            segments: graph.segment_storage.iter().map(|s| s.0.len()).collect(),
+            markings: graph.segment_storage.iter().map(|s| s.1 as usize).sum(),
+            frontier,
+            elapsed,
+        }
+    }
+}
+
+pub trait ExploreObserver: Send + Sync {
+    fn progress(&self, stats: &ExploreStats);
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::Mutex,
+};
+
+/// Hash map split into shards with their own lock, shared by the workers of the exploration
+/// a key is always in the shard of its hash, so only equal keys wait for each other
+#[derive(Debug)]
+pub struct ShardedMap<K, V> {
+    shards: Vec<Mutex<HashMap<K, V>>>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V: Clone> ShardedMap<K, V> {
+    pub fn new(shards: usize) -> Self {
+        ShardedMap {
+            shards: (0..shards.max(1)).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// the value of the key and false, or the new value from insert and true
+    /// insert runs under the lock of the shard, so only one caller inserts a key,
+    /// None if the key is new and insert returned None
+    pub fn get_or_insert_with(
+        &self,
+        key: K,
+        insert: impl FnOnce() -> Option<V>,
+    ) -> Option<(V, bool)> {
+        let shard = self.hasher.hash_one(&key) as usize % self.shards.len();
+        let mut shard = self.shards[shard].lock().unwrap();
+        if let Some(value) = shard.get(&key) {
+            return Some((value.clone(), false));
+        }
+        let value = insert()?;
+        shard.insert(key, value.clone());
+        Some((value, true))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::ShardedMap;
+
+    #[test]
+    fn test_sharded_map() {
+        // every key gets exactly one id although all threads insert all keys
+        let map = ShardedMap::new(4);
+        let next = AtomicUsize::new(0);
+        let ids = thread::scope(|s| {
+            let workers = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        (0..1000)
+                            .map(|k| {
+                                let claim = || Some(next.fetch_add(1, Ordering::Relaxed));
+                                map.get_or_insert_with(k % 100, claim).unwrap().0
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .map(|w| w.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(map.len(), 100);
+        assert_eq!(next.load(Ordering::Relaxed), 100);
+        assert!(ids.windows(2).all(|w| w[0] == w[1]));
+
+        // a refused insert leaves the key out
+        assert_eq!(map.get_or_insert_with(100, || None), None);
+        assert_eq!(map.get_or_insert_with(100, || Some(7)), Some((7, true)));
+        assert_eq!(map.get_or_insert_with(100, || Some(8)), Some((7, false)));
+        assert!(!map.is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::modular_net::{ModularPetrinet, ModuleId, TransitionId};
+
+use super::{
+    graph::{Graph, Id, Marking, MarkingId},
+    symmetry::assign,
+};
+
+pub type StateId = usize;
+
+/// Global state of the composed net
+/// a sync node and one marking of the corresponding segment per module
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GlobalState {
+    pub sync_id: Id,
+    pub marking_ids: Vec<MarkingId>,
+}
+
+/// Edge between two global states
+/// for extern transitions module_id is the first module the transition exists in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateEdge {
+    pub module_id: ModuleId,
+    pub transition_id: TransitionId,
+    pub state_id: StateId,
+}
+
+/// Flat state space, expanded from the segments of a sync graph
+#[derive(Debug)]
+pub struct StateSpace {
+    pub states: Vec<GlobalState>,
+    /// successors of the expanded states, empty for states not expanded yet
+    pub edges: Vec<Vec<StateEdge>>,
+    expanded: Vec<bool>,
+    known: HashMap<GlobalState, StateId>,
+}
+
+impl StateSpace {
+    /// only the initial state (0) of the sync graph, the others are added by expand_state
+    pub fn initial(graph: &Graph) -> Self {
+        let mut space = StateSpace {
+            states: vec![],
+            edges: vec![],
+            expanded: vec![],
+            known: HashMap::new(),
+        };
+        if let Some(node) = graph.sync_graph.first() {
+            let initial = GlobalState {
+                sync_id: 0,
+                marking_ids: node
+                    .segment_ids
+                    .iter()
+                    .enumerate()
+                    .map(|(m_id, &seg_id)| {
+                        graph.segment_storage[m_id].0[seg_id as usize]
+                            .0
+                            .marking_offset
+                    })
+                    .collect(),
+            };
+            space.insert(initial);
+        }
+        space
+    }
+
+    fn insert(&mut self, state: GlobalState) -> StateId {
+        if let Some(&id) = self.known.get(&state) {
+            return id;
+        }
+        let id = self.states.len();
+        self.known.insert(state.clone(), id);
+        self.states.push(state);
+        self.edges.push(vec![]);
+        self.expanded.push(false);
+        id
+    }
+
+    /// successors of a state, new states are appended
+    /// intern transitions are taken from the segment edges, extern transitions follow the sync
+    /// edges and are fired locally in the touched modules, the result is searched in the target
+    pub fn expand_state(
+        &mut self,
+        net: &ModularPetrinet,
+        graph: &Graph,
+        s_id: StateId,
+    ) -> &[StateEdge] {
+        if !self.expanded[s_id] {
+            let successors = self.successors(net, graph, s_id);
+            self.edges[s_id] = successors
+                .into_iter()
+                .map(|(module_id, transition_id, next)| StateEdge {
+                    module_id,
+                    transition_id,
+                    state_id: self.insert(next),
+                })
+                .collect();
+            self.expanded[s_id] = true;
+        }
+        &self.edges[s_id]
+    }
+
+    fn successors(
+        &self,
+        net: &ModularPetrinet,
+        graph: &Graph,
+        s_id: StateId,
+    ) -> Vec<(ModuleId, TransitionId, GlobalState)> {
+        let state = &self.states[s_id];
+        let sync_node = &graph.sync_graph[state.sync_id as usize];
+        let mut successors = vec![];
+
+        // intern transitions
+        for (m_id, &marking_id) in state.marking_ids.iter().enumerate() {
+            let seg_id = sync_node.segment_ids[m_id];
+            for edge in &graph
+                .graph_marking(m_id as ModuleId, seg_id, marking_id)
+                .edges
+            {
+                let mut next = state.clone();
+                next.marking_ids[m_id] = edge.graph_marking_id;
+                successors.push((m_id as ModuleId, edge.transition_id, next));
+            }
+        }
+
+        // extern transitions
+        'e: for edge in &sync_node.edges {
+            let target = &graph.sync_graph[edge.sync_marking_id as usize];
+            let mut next = GlobalState {
+                sync_id: edge.sync_marking_id,
+                marking_ids: state.marking_ids.clone(),
+            };
+            let touched = &net.extern_t_overview[edge.transition_id as usize];
+            if !graph.symmetry.is_empty() {
+                // the target is a permutation of the real successor
+                let mut markings = (0..net.modules.len() as ModuleId)
+                    .map(|m_id| self.marking(graph, s_id, m_id))
+                    .collect::<Vec<_>>();
+                for &m_id in touched {
+                    let module = &net.modules[m_id as usize];
+                    let marking = &markings[m_id as usize];
+                    if !module.is_enabled(marking, edge.transition_id) {
+                        continue 'e;
+                    }
+                    markings[m_id as usize] = module.fire(marking, edge.transition_id);
+                }
+                next.marking_ids = assign(graph, target, &markings)
+                    .expect("marking after extern transition is missing in target segment");
+                successors.push((touched[0], edge.transition_id, next));
+                continue;
+            }
+            for &m_id in touched {
+                let module = &net.modules[m_id as usize];
+                let marking = self.marking(graph, s_id, m_id);
+                if !module.is_enabled(&marking, edge.transition_id) {
+                    continue 'e;
+                }
+                let new_marking = module.fire(&marking, edge.transition_id);
+                let segment = &graph.segment_storage[m_id as usize].0
+                    [target.segment_ids[m_id as usize] as usize]
+                    .0;
+                next.marking_ids[m_id as usize] = segment
+                    .search_equal_marking(&new_marking)
+                    .expect("marking after extern transition is missing in target segment");
+            }
+            successors.push((touched[0], edge.transition_id, next));
+        }
+        successors
+    }
+
+    /// local marking of a module in a global state
+    pub fn marking(&self, graph: &Graph, state: StateId, m_id: ModuleId) -> Marking {
+        let s = &self.states[state];
+        let seg_id = graph.sync_graph[s.sync_id as usize].segment_ids[m_id as usize];
+        graph.marking(m_id, seg_id, s.marking_ids[m_id as usize])
+    }
+
+    /// predecessors of every state
+    pub fn predecessors(&self) -> Vec<Vec<StateId>> {
+        let mut pred = vec![vec![]; self.states.len()];
+        for (s, edges) in self.edges.iter().enumerate() {
+            for edge in edges {
+                pred[edge.state_id].push(s);
+            }
+        }
+        pred
+    }
+
+    pub fn write(&self, net: &ModularPetrinet, out: &mut dyn Write) -> io::Result<()> {
+        for (id, state) in self.states.iter().enumerate() {
+            writeln!(
+                out,
+                "StateId: {} | SyncId: {} | Markings: {:?}",
+                id, state.sync_id, state.marking_ids
+            )?;
+            for edge in &self.edges[id] {
+                writeln!(
+                    out,
+                    "  {} -> {}",
+                    net.transition_name(edge.module_id, edge.transition_id),
+                    edge.state_id
+                )?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Expand the global state space from the sync graph
+/// the state 0 is the initial state
+pub fn expand(net: &ModularPetrinet, graph: &Graph) -> StateSpace {
+    let mut space = StateSpace::initial(graph);
+    let mut to_explore = (0..space.states.len()).collect::<Vec<_>>();
+    while let Some(s_id) = to_explore.pop() {
+        let known = space.states.len();
+        space.expand_state(net, graph, s_id);
+        to_explore.extend(known..space.states.len());
+    }
+    space
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_reachability_graph::build_graph::build_sync_reachability_graph, testing::example,
+    };
+
+    use super::expand;
+
+    #[test]
+    fn test_expand_choice() {
+        let net = example("ba2022/P005.pnawir");
+        let graph = build_sync_reachability_graph(&net);
+        let space = expand(&net, &graph);
+        assert_eq!(space.states.len(), 3);
+        assert_eq!(space.edges[0].len(), 2);
+        assert!(space.edges[1].is_empty());
+        assert!(space.edges[2].is_empty());
+        let mut out = vec![];
+        space.write(&net, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("StateId: 0 | SyncId: 0"));
+        assert_eq!(text.matches(" -> ").count(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::modular_net::{PetrinetModul, TransitionId};
+
+use super::{graph::Marking, options::Reduction};
+
+/// Enabled intern transitions to fire in the marking, in the order of firable
+/// with a reduction these are the enabled intern transitions of the smallest stubborn set found
+pub fn stubborn_set(
+    module: &PetrinetModul,
+    marking: &Marking,
+    firable: &[TransitionId],
+    intern_start: TransitionId,
+    reduction: Reduction,
+) -> Vec<TransitionId> {
+    let enabled = firable
+        .iter()
+        .copied()
+        .filter(|&t| t >= intern_start)
+        .collect::<Vec<_>>();
+    if reduction == Reduction::None || enabled.len() <= 1 {
+        return enabled;
+    }
+
+    // the extern transitions present in the module, with Interface they are part of every set
+    let externs = (0..intern_start)
+        .filter(|&t| {
+            let t = &module.transitions[t as usize];
+            t.input_places.len() + t.output_places.len() > 0
+        })
+        .collect::<Vec<_>>();
+    let visible = match reduction {
+        Reduction::Interface => externs.clone(),
+        _ => vec![],
+    };
+
+    // try every enabled transition as key
+    let mut best = enabled.clone();
+    for &key in &enabled {
+        let mut seeds = visible.clone();
+        seeds.push(key);
+        let mut in_set = closure(module, marking, seeds);
+        // an extern transition in the set may be enabled by another module at any time,
+        // with all of them the set is stubborn for the composed net and keeps its deadlocks
+        if visible.is_empty() && (0..intern_start).any(|t| in_set[t as usize]) {
+            let mut seeds = externs.clone();
+            seeds.push(key);
+            in_set = closure(module, marking, seeds);
+        }
+        let reduced = enabled
+            .iter()
+            .copied()
+            .filter(|&t| in_set[t as usize])
+            .collect::<Vec<_>>();
+        if reduced.len() < best.len() {
+            best = reduced;
+        }
+        if best.len() == 1 {
+            break;
+        }
+    }
+    best
+}
+
+/// Smallest set containing the seeds with
+/// enabled t: every transition taking tokens from an input place of t
+/// disabled t: every transition putting tokens into one input place with too few tokens
+fn closure(module: &PetrinetModul, marking: &Marking, seeds: Vec<TransitionId>) -> Vec<bool> {
+    let mut in_set = vec![false; module.transitions.len()];
+    seeds.iter().for_each(|&t| in_set[t as usize] = true);
+    let mut to_explore = seeds;
+
+    while let Some(t_id) = to_explore.pop() {
+        let t = &module.transitions[t_id as usize];
+        let added: Vec<TransitionId> = if module.is_enabled(marking, t_id) {
+            t.input_places
+                .iter()
+                .flat_map(|&(p, _)| &module.places[p as usize].output_transitions)
+                .map(|&(u, _)| u)
+                .collect()
+        } else {
+            // the place with the fewest producers
+            t.input_places
+                .iter()
+                .filter(|&&(p, w)| marking.count(p) < w)
+                .map(|&(p, _)| &module.places[p as usize].input_transitions)
+                .min_by_key(|producers| producers.len())
+                .map_or(vec![], |producers| {
+                    producers.iter().map(|&(u, _)| u).collect()
+                })
+        };
+        for u in added {
+            if !in_set[u as usize] {
+                in_set[u as usize] = true;
+                to_explore.push(u);
+            }
+        }
+    }
+    in_set
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_reachability_graph::{
+            build_graph::build_sync_reachability_graph_with,
+            options::{ExploreOptions, Reduction},
+            state_space::expand,
+        },
+        testing::{example, net},
+    };
+
+    #[test]
+    fn test_stubborn_sets() {
+        let net = example("ba2022/P002-1.pnawir");
+        let markings = |reduction| {
+            let options = ExploreOptions {
+                reduction,
+                ..Default::default()
+            };
+            let graph = build_sync_reachability_graph_with(&net, &options);
+            let space = expand(&net, &graph);
+            let mut dead = (0..space.states.len())
+                .filter(|&s| space.edges[s].is_empty())
+                .map(|s| space.marking(&graph, s, 0))
+                .collect::<Vec<_>>();
+            dead.sort();
+            (graph.segment_storage[0].1, dead)
+        };
+
+        let full = markings(Reduction::None);
+        let reduced = markings(Reduction::Deadlocks);
+        assert_eq!(full.0, 14);
+        assert!(reduced.0 < full.0);
+        assert_eq!(reduced.1, full.1);
+
+        let net = example("ba2022/P001-1.pnawir");
+        let options = ExploreOptions {
+            reduction: Reduction::Deadlocks,
+            ..Default::default()
+        };
+        let graph = build_sync_reachability_graph_with(&net, &options);
+        let space = expand(&net, &graph);
+        assert_eq!(space.states.len(), 9);
+        assert_eq!(space.edges.iter().filter(|e| e.is_empty()).count(), 1);
+    }
+
+    #[test]
+    fn test_global_deadlocks() {
+        // B2 conflicts with t0 only, but t1 enables t0 after B0 through M1
+        let net = net("{\n    t0: M1.a3, M2.b2 -> M1.a0, M2.b1\n\
+            t1: M1.a0, M2.b3 -> M1.a1, M2.b1\n}\n\n\
+            M1 {\n    a0(1), a1, a2(1), a3\n    A0: a1 -> a0, a2\n\
+            A1: a1 -> a3, a2\n    A2: a3, a1 -> a1\n}\n\n\
+            M2 {\n    b0(1), b1, b2(1), b3\n    B0: b0 -> b3, b1\n\
+            B1: b0 -> b1, b3\n    B2: b2 -> b1, b0\n}\n");
+        let deadlocks = |reduction| {
+            let options = ExploreOptions {
+                reduction,
+                ..Default::default()
+            };
+            let graph = build_sync_reachability_graph_with(&net, &options);
+            let space = expand(&net, &graph);
+            let mut dead = (0..space.states.len())
+                .filter(|&s| space.edges[s].is_empty())
+                .map(|s| (space.marking(&graph, s, 0), space.marking(&graph, s, 1)))
+                .collect::<Vec<_>>();
+            dead.sort();
+            dead.dedup();
+            dead
+        };
+
+        let full = deadlocks(Reduction::None);
+        assert!(!full.is_empty());
+        assert_eq!(deadlocks(Reduction::Deadlocks), full);
+        assert_eq!(deadlocks(Reduction::Interface), full);
+    }
+}
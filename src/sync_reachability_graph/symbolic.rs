@@ -0,0 +1,407 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+};
+
+use log::info;
+
+use crate::modular_net::{ModularPetrinet, ModuleId, PetrinetModul, PlaceId, TransitionId};
+
+use super::{
+    bdd::{Bdd, BddRef, Var, FALSE, TRUE},
+    graph::{Marking, PlaceCount, SegmentId, SyncEdge, SyncMarking},
+    options::{ExploreOptions, Limit},
+};
+
+/// Sync graph with the segments stored as Bdds over the places of their module
+/// only for 1-safe nets, the place p is the variable 2p, its successor 2p + 1
+/// it can only be written out, the state space and the model checkers need the explicit Graph
+#[derive(Debug)]
+pub struct SymbolicGraph {
+    pub bdd: Bdd,
+    pub sync_graph: Vec<SyncMarking>,
+    /// markings of every segment of every module
+    pub segments: Vec<Vec<BddRef>>,
+    modules: Vec<SymbolicModule>,
+    /// the limit which stopped the exploration, None if the graph is complete
+    pub incomplete: Option<Limit>,
+}
+
+#[derive(Debug)]
+struct SymbolicModule {
+    places: usize,
+    /// relation of all intern transitions
+    intern: BddRef,
+    /// markings where an intern transition puts a second token into a place
+    intern_unsafe: BddRef,
+    /// (enabled, relation, unsafe) of the extern transitions existing in the module
+    extern_t: Vec<Option<(BddRef, BddRef, BddRef)>>,
+}
+
+fn current(p: PlaceId) -> Var {
+    2 * p
+}
+
+fn next(p: PlaceId) -> Var {
+    2 * p + 1
+}
+
+impl SymbolicModule {
+    fn new(
+        bdd: &mut Bdd,
+        module: &PetrinetModul,
+        intern_start: TransitionId,
+    ) -> Result<Self, String> {
+        let mut intern = FALSE;
+        let mut intern_unsafe = FALSE;
+        let mut extern_t = vec![None; intern_start as usize];
+        for t in &module.transitions {
+            if t.input_places.len() + t.output_places.len() == 0 {
+                continue;
+            }
+            let arcs = t.input_places.iter().chain(&t.output_places);
+            if arcs.clone().any(|&(_, w)| w != 1) {
+                return Err(format!("transition {} has arc weights above 1", t.name));
+            }
+            let enabled = t.input_places.iter().fold(TRUE, |f, &(p, _)| {
+                let v = bdd.var(current(p));
+                bdd.and(f, v)
+            });
+            // an output place which is no input place is already marked
+            let marked_output = t
+                .output_places
+                .iter()
+                .filter(|&&(p, _)| t.input_places.iter().all(|&(q, _)| q != p))
+                .fold(FALSE, |f, &(p, _)| {
+                    let v = bdd.var(current(p));
+                    bdd.or(f, v)
+                });
+            let contact = bdd.and(enabled, marked_output);
+            // from the last place, so the conjunction grows at the top
+            let mut relation = TRUE;
+            for p in (0..module.places.len() as PlaceId).rev() {
+                let input = t.input_places.iter().any(|&(q, _)| q == p);
+                let output = t.output_places.iter().any(|&(q, _)| q == p);
+                let (c, n) = (bdd.var(current(p)), bdd.var(next(p)));
+                let constraint = match (input, output) {
+                    (true, false) => {
+                        let not_n = bdd.not(n);
+                        bdd.and(c, not_n)
+                    }
+                    (true, true) => bdd.and(c, n),
+                    (false, true) => n,
+                    (false, false) => bdd.equiv(c, n),
+                };
+                relation = bdd.and(relation, constraint);
+            }
+            if t.id < intern_start {
+                extern_t[t.id as usize] = Some((enabled, relation, contact));
+            } else {
+                intern = bdd.or(intern, relation);
+                intern_unsafe = bdd.or(intern_unsafe, contact);
+            }
+        }
+        Ok(SymbolicModule {
+            places: module.places.len(),
+            intern,
+            intern_unsafe,
+            extern_t,
+        })
+    }
+
+    /// all markings reachable by the intern transitions
+    fn reach(&self, bdd: &mut Bdd, start: BddRef, name: &str) -> Result<BddRef, String> {
+        let mut states = start;
+        loop {
+            if bdd.and(states, self.intern_unsafe) != FALSE {
+                return Err(format!("module {} is not 1-safe", name));
+            }
+            let successors = image(bdd, states, self.intern);
+            let next = bdd.or(states, successors);
+            if next == states {
+                return Ok(states);
+            }
+            states = next;
+        }
+    }
+}
+
+/// successor markings of states under the relation
+fn image(bdd: &mut Bdd, states: BddRef, relation: BddRef) -> BddRef {
+    let both = bdd.and(states, relation);
+    let successors = bdd.exists(both, &|v| v % 2 == 0);
+    bdd.rename(successors, &|v| v - 1)
+}
+
+fn cube(bdd: &mut Bdd, marking: &Marking, places: usize) -> BddRef {
+    (0..places as PlaceId).rev().fold(TRUE, |f, p| {
+        let v = if marking.count(p) > 0 {
+            bdd.var(current(p))
+        } else {
+            bdd.nvar(current(p))
+        };
+        bdd.and(v, f)
+    })
+}
+
+/// Build the sync graph with symbolic segments, breadth first
+/// Err if the net is not 1-safe, checked during the exploration
+pub fn build_symbolic_graph(net: &ModularPetrinet) -> Result<SymbolicGraph, String> {
+    build_symbolic_graph_with(net, &ExploreOptions::default())
+}
+
+/// Build the sync graph with symbolic segments within the sync node limit, deadline and cancel,
+/// the other options do not apply to symbolic segments
+pub fn build_symbolic_graph_with(
+    net: &ModularPetrinet,
+    options: &ExploreOptions,
+) -> Result<SymbolicGraph, String> {
+    for (module, marking) in net.modules.iter().zip(&net.markings) {
+        if marking.place_counts.iter().any(|pc| pc.count > 1) {
+            return Err(format!("module {} is not 1-safe", module.name));
+        }
+    }
+
+    let mut bdd = Bdd::new();
+    let modules = net
+        .modules
+        .iter()
+        .map(|m| SymbolicModule::new(&mut bdd, m, net.intern_transition_start))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut segments: Vec<Vec<BddRef>> = vec![vec![]; net.modules.len()];
+    let mut segment_ids: Vec<HashMap<BddRef, SegmentId>> = vec![HashMap::new(); net.modules.len()];
+
+    let mut initial = vec![];
+    for (m_id, module) in modules.iter().enumerate() {
+        let start = cube(&mut bdd, &net.markings[m_id], module.places);
+        let states = module.reach(&mut bdd, start, &net.modules[m_id].name)?;
+        initial.push(store(&mut segments[m_id], &mut segment_ids[m_id], states));
+    }
+
+    let mut sync_graph = vec![SyncMarking {
+        segment_ids: initial.clone(),
+        edges: vec![],
+    }];
+    let mut sync_ids = HashMap::from([(initial, 0)]);
+    let mut to_explore = VecDeque::from([0]);
+    let mut incomplete = None;
+
+    while let Some(sync_id) = to_explore.pop_front() {
+        if let Some(limit) = options.interrupted() {
+            incomplete = Some(limit);
+            break;
+        }
+        let c_segments = sync_graph[sync_id].segment_ids.clone();
+        let mut edges = vec![];
+        't: for (t_id, touched) in net.extern_t_overview.iter().enumerate() {
+            // enabled in every touched module
+            let mut enabled = vec![];
+            for &m_id in touched {
+                let m_id = m_id as usize;
+                let states = segments[m_id][c_segments[m_id] as usize];
+                let (pre, _, _) = modules[m_id].extern_t[t_id].unwrap();
+                let f = bdd.and(states, pre);
+                if f == FALSE {
+                    continue 't;
+                }
+                enabled.push(f);
+            }
+            // only once enabled in all touched modules
+            for (&m_id, &f) in touched.iter().zip(&enabled) {
+                let (_, _, contact) = modules[m_id as usize].extern_t[t_id].unwrap();
+                if bdd.and(f, contact) != FALSE {
+                    return Err(format!(
+                        "module {} is not 1-safe",
+                        net.modules[m_id as usize].name
+                    ));
+                }
+            }
+
+            let mut new_segments = c_segments.clone();
+            for (&m_id, f) in touched.iter().zip(enabled) {
+                let m_id = m_id as usize;
+                let (_, relation, _) = modules[m_id].extern_t[t_id].unwrap();
+                let start = image(&mut bdd, f, relation);
+                let states = modules[m_id].reach(&mut bdd, start, &net.modules[m_id].name)?;
+                new_segments[m_id] = store(&mut segments[m_id], &mut segment_ids[m_id], states);
+            }
+
+            let next_id = sync_graph.len();
+            let target = match sync_ids.get(&new_segments) {
+                Some(&x) => x,
+                None if options.max_sync_nodes.is_some_and(|max| next_id >= max) => {
+                    incomplete = Some(Limit::SyncNodes);
+                    continue;
+                }
+                None => {
+                    sync_ids.insert(new_segments.clone(), next_id);
+                    next_id
+                }
+            };
+            if target == next_id {
+                sync_graph.push(SyncMarking {
+                    segment_ids: new_segments,
+                    edges: vec![],
+                });
+                to_explore.push_back(next_id);
+            }
+            edges.push(SyncEdge::new(t_id as TransitionId, target as u32));
+        }
+        sync_graph[sync_id].edges = edges;
+    }
+
+    info!(
+        "symbolic sync graph: {} sync nodes, {} Bdd nodes",
+        sync_graph.len(),
+        bdd.size()
+    );
+    Ok(SymbolicGraph {
+        bdd,
+        sync_graph,
+        segments,
+        modules,
+        incomplete,
+    })
+}
+
+/// equal Bdds are equal segments
+fn store(
+    segments: &mut Vec<BddRef>,
+    segment_ids: &mut HashMap<BddRef, SegmentId>,
+    states: BddRef,
+) -> SegmentId {
+    *segment_ids.entry(states).or_insert_with(|| {
+        segments.push(states);
+        segments.len() as SegmentId - 1
+    })
+}
+
+impl SymbolicGraph {
+    /// number of markings of a segment
+    pub fn marking_count(&self, m_id: ModuleId, seg_id: SegmentId) -> u128 {
+        let places = self.modules[m_id as usize].places as Var;
+        let states = self.segments[m_id as usize][seg_id as usize];
+        // the successor variables are free
+        self.bdd.sat_count(states, 2 * places) >> places
+    }
+
+    /// explicit markings of a segment
+    pub fn markings(&self, m_id: ModuleId, seg_id: SegmentId) -> Vec<Marking> {
+        let places = self.modules[m_id as usize].places as PlaceId;
+        let vars = (0..places).map(current).collect::<Vec<_>>();
+        let states = self.segments[m_id as usize][seg_id as usize];
+        self.bdd
+            .assignments(states, &vars)
+            .into_iter()
+            .map(|marked| Marking {
+                place_counts: marked
+                    .into_iter()
+                    .map(|v| PlaceCount::new(v / 2, 1))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn print(&self, net: &ModularPetrinet) {
+        self.write(net, &mut io::stdout().lock())
+            .expect("writing to stdout");
+    }
+
+    pub fn write(&self, net: &ModularPetrinet, out: &mut dyn Write) -> io::Result<()> {
+        for (id, node) in self.sync_graph.iter().enumerate() {
+            let counts = node
+                .segment_ids
+                .iter()
+                .enumerate()
+                .map(|(m_id, &seg_id)| self.marking_count(m_id as ModuleId, seg_id))
+                .collect::<Vec<_>>();
+            writeln!(
+                out,
+                "SyncId: {} | Segments: {:?} | Markings: {:?}",
+                id, node.segment_ids, counts
+            )?;
+            for edge in &node.edges {
+                let m_id = net.extern_t_overview[edge.transition_id as usize][0];
+                writeln!(
+                    out,
+                    "  {} -> {}",
+                    net.transition_name(m_id, edge.transition_id),
+                    edge.sync_marking_id
+                )?;
+            }
+        }
+        writeln!(out, "Bdd Nodes: {}", self.bdd.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        modular_net::ModuleId,
+        sync_reachability_graph::{
+            build_graph::build_sync_reachability_graph,
+            options::{CancelToken, ExploreOptions, Limit},
+        },
+        testing::example,
+    };
+
+    use super::{build_symbolic_graph, build_symbolic_graph_with};
+
+    #[test]
+    fn test_symbolic_graph() {
+        let inputs = [
+            "ba2022/P002.pnawir",
+            "ba2022/P004.pnawir",
+            "ba2022/P005.pnawir",
+            "ba2022/P006.pnawir",
+            "symmetry/S001.pnawir",
+        ];
+        for input in inputs {
+            let net = example(input);
+            let explicit = build_sync_reachability_graph(&net);
+            let symbolic = build_symbolic_graph(&net).unwrap();
+            assert_eq!(symbolic.sync_graph.len(), explicit.sync_graph.len());
+
+            // the same markings in every sync node
+            for (s, e) in symbolic.sync_graph.iter().zip(&explicit.sync_graph) {
+                for m_id in 0..net.modules.len() {
+                    let mut a = symbolic.markings(m_id as ModuleId, s.segment_ids[m_id]);
+                    let segment = &explicit.segment_storage[m_id].0[e.segment_ids[m_id] as usize].0;
+                    let mut b = segment
+                        .markings
+                        .iter()
+                        .map(|m| segment.marking(m.id))
+                        .collect::<Vec<_>>();
+                    a.sort();
+                    b.sort();
+                    assert_eq!(a, b);
+                    let count = symbolic.marking_count(m_id as ModuleId, s.segment_ids[m_id]);
+                    assert_eq!(count, b.len() as u128);
+                }
+            }
+        }
+
+        // v2: p3 -> p1(2)
+        let net = example("ba2022/P001.pnawir");
+        assert!(build_symbolic_graph(&net).is_err());
+
+        let net = example("symmetry/S001.pnawir");
+        assert_eq!(build_symbolic_graph(&net).unwrap().incomplete, None);
+        let options = ExploreOptions {
+            max_sync_nodes: Some(3),
+            ..Default::default()
+        };
+        let graph = build_symbolic_graph_with(&net, &options).unwrap();
+        assert_eq!(graph.sync_graph.len(), 3);
+        assert_eq!(graph.incomplete, Some(Limit::SyncNodes));
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let options = ExploreOptions {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let graph = build_symbolic_graph_with(&net, &options).unwrap();
+        assert_eq!(graph.incomplete, Some(Limit::Cancelled));
+    }
+}
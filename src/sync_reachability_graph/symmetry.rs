@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use crate::modular_net::{ModularPetrinet, ModuleId, PlaceId, TransitionId, Weight};
+
+use super::{
+    compact_marking::CompactMarking,
+    graph::{Graph, Marking, MarkingId, SegmentId, SyncMarking},
+};
+
+/// arcs of a transition in one module: (inputs, outputs)
+type Arcs = (Vec<(PlaceId, Weight)>, Vec<(PlaceId, Weight)>);
+
+/// Classes of interchangeable modules
+/// modules are identical if places and intern transitions in declaration order have the same arcs
+/// and the same initial marking, swapping two of them (and their extern transitions) maps the net
+/// onto itself
+pub fn symmetric_modules(net: &ModularPetrinet) -> Vec<Vec<ModuleId>> {
+    let mut classes: Vec<Vec<ModuleId>> = vec![];
+    for module in &net.modules {
+        match classes.iter_mut().find(|c| swappable(net, c[0], module.id)) {
+            Some(class) => class.push(module.id),
+            None => classes.push(vec![module.id]),
+        }
+    }
+    classes.retain(|c| c.len() > 1);
+    classes
+}
+
+fn swappable(net: &ModularPetrinet, a: ModuleId, b: ModuleId) -> bool {
+    let (ma, mb) = (&net.modules[a as usize], &net.modules[b as usize]);
+    if ma.places.len() != mb.places.len()
+        || ma.transitions.len() != mb.transitions.len()
+        || net.markings[a as usize] != net.markings[b as usize]
+    {
+        return false;
+    }
+    let intern = net.intern_transition_start as usize..ma.transitions.len();
+    if intern
+        .clone()
+        .any(|t| arcs(net, a, t as TransitionId) != arcs(net, b, t as TransitionId))
+    {
+        return false;
+    }
+
+    // every extern transition needs a partner with the arcs of a and b exchanged
+    let signature = |t: TransitionId, swap: bool| {
+        (0..net.modules.len() as ModuleId)
+            .map(|m| match m {
+                _ if swap && m == a => arcs(net, b, t),
+                _ if swap && m == b => arcs(net, a, t),
+                _ => arcs(net, m, t),
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut partners: HashMap<Vec<Arcs>, usize> = HashMap::new();
+    for t in 0..net.intern_transition_start {
+        *partners.entry(signature(t, false)).or_default() += 1;
+    }
+    for t in 0..net.intern_transition_start {
+        match partners.get_mut(&signature(t, true)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn arcs(net: &ModularPetrinet, m_id: ModuleId, t_id: TransitionId) -> Arcs {
+    let t = &net.modules[m_id as usize].transitions[t_id as usize];
+    let mut arcs = (t.input_places.clone(), t.output_places.clone());
+    arcs.0.sort_unstable();
+    arcs.1.sort_unstable();
+    arcs
+}
+
+/// Keys of the segments by their markings, equal segments of identical modules get the same key
+#[derive(Debug, Default)]
+pub struct SegmentKeys {
+    ids: HashMap<Vec<CompactMarking>, u32>,
+    /// key of every segment of every module
+    keys: Vec<Vec<u32>>,
+}
+
+impl SegmentKeys {
+    /// the markings of the segment in sorted order
+    pub fn insert(&mut self, m_id: ModuleId, seg_id: SegmentId, markings: Vec<CompactMarking>) {
+        let next = self.ids.len() as u32;
+        let key = *self.ids.entry(markings).or_insert(next);
+        if self.keys.len() <= m_id as usize {
+            self.keys.resize(m_id as usize + 1, vec![]);
+        }
+        let keys = &mut self.keys[m_id as usize];
+        if keys.len() <= seg_id as usize {
+            keys.resize(seg_id as usize + 1, 0);
+        }
+        keys[seg_id as usize] = key;
+    }
+
+    /// equal for sync nodes which differ only by a permutation within the classes
+    pub fn canonical(&self, classes: &[Vec<ModuleId>], segment_ids: &[SegmentId]) -> Vec<u32> {
+        let mut key = segment_ids
+            .iter()
+            .enumerate()
+            .map(|(m_id, &seg_id)| self.keys[m_id][seg_id as usize])
+            .collect::<Vec<_>>();
+        for class in classes {
+            let mut sorted = class.iter().map(|&m| key[m as usize]).collect::<Vec<_>>();
+            sorted.sort_unstable();
+            for (&m, k) in class.iter().zip(sorted) {
+                key[m as usize] = k;
+            }
+        }
+        key
+    }
+}
+
+/// A proposition over the modules has to give the same value for every permutation of a class,
+/// a quotient graph answers wrong for propositions distinguishing identical modules
+pub fn check_preserved(
+    net: &ModularPetrinet,
+    classes: &[Vec<ModuleId>],
+    modules: &[ModuleId],
+    what: &str,
+) -> Result<(), String> {
+    match modules
+        .iter()
+        .find(|m| classes.iter().any(|c| c.contains(m)))
+    {
+        Some(&m) => Err(format!(
+            "{} distinguishes the symmetric module {}, check it without symmetry",
+            what, net.modules[m as usize].name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Marking ids of the markings in the sync node, modules of a class may be permuted
+/// None if the markings do not fit into the segments
+pub fn assign(graph: &Graph, node: &SyncMarking, markings: &[Marking]) -> Option<Vec<MarkingId>> {
+    let candidates = (0..markings.len() as ModuleId)
+        .map(|m| {
+            graph
+                .symmetry
+                .iter()
+                .find(|c| c.contains(&m))
+                .cloned()
+                .unwrap_or(vec![m])
+        })
+        .collect::<Vec<_>>();
+    let mut result = vec![None; markings.len()];
+    if assign_from(graph, node, markings, &candidates, 0, &mut result) {
+        Some(result.into_iter().map(|id| id.unwrap()).collect())
+    } else {
+        None
+    }
+}
+
+/// backtracking, the marking of module i is placed into one of its candidates
+fn assign_from(
+    graph: &Graph,
+    node: &SyncMarking,
+    markings: &[Marking],
+    candidates: &[Vec<ModuleId>],
+    i: usize,
+    result: &mut Vec<Option<MarkingId>>,
+) -> bool {
+    if i == markings.len() {
+        return true;
+    }
+    for &j in &candidates[i] {
+        if result[j as usize].is_some() {
+            continue;
+        }
+        let segment = &graph.segment_storage[j as usize].0[node.segment_ids[j as usize] as usize].0;
+        if let Some(id) = segment.search_equal_marking(&markings[i]) {
+            result[j as usize] = Some(id);
+            if assign_from(graph, node, markings, candidates, i + 1, result) {
+                return true;
+            }
+            result[j as usize] = None;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sync_reachability_graph::{
+            build_graph::build_sync_reachability_graph_with, options::ExploreOptions,
+            state_space::expand,
+        },
+        testing::example,
+    };
+
+    use super::symmetric_modules;
+
+    #[test]
+    fn test_symmetry() {
+        // three identical workers sharing a mutex
+        let net = example("symmetry/S001.pnawir");
+        assert_eq!(symmetric_modules(&net), vec![vec![1, 2, 3]]);
+
+        let full = build_sync_reachability_graph_with(&net, &ExploreOptions::default());
+        let options = ExploreOptions {
+            symmetry: true,
+            ..Default::default()
+        };
+        let reduced = build_sync_reachability_graph_with(&net, &options);
+        assert_eq!(reduced.symmetry, vec![vec![1, 2, 3]]);
+        assert!(reduced.sync_graph.len() < full.sync_graph.len());
+
+        let full_space = expand(&net, &full);
+        let reduced_space = expand(&net, &reduced);
+        assert!(reduced_space.states.len() < full_space.states.len());
+        // no deadlock in both
+        assert!(full_space.edges.iter().all(|e| !e.is_empty()));
+        assert!(reduced_space.edges.iter().all(|e| !e.is_empty()));
+
+        // the modules with the same name in P006a differ
+        let net = example("ba2022/P006a.pnawir");
+        assert!(symmetric_modules(&net).is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use super::options::Strategy;
+
+/// Items to explore, taken in the order of the strategy
+#[derive(Debug)]
+pub struct Worklist<T> {
+    items: VecDeque<T>,
+    strategy: Strategy,
+    /// xorshift state for Strategy::Random
+    rng: u64,
+}
+
+impl<T> Worklist<T> {
+    pub fn new(strategy: Strategy) -> Self {
+        let rng = match strategy {
+            // splitmix64 of the seed, the state must not be 0
+            Strategy::Random(seed) => {
+                let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                (z ^ (z >> 31)).max(1)
+            }
+            _ => 1,
+        };
+        Worklist {
+            items: VecDeque::new(),
+            strategy,
+            rng,
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.items.push_back(item);
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        match self.strategy {
+            Strategy::Bfs => self.items.pop_front(),
+            Strategy::Dfs => self.items.pop_back(),
+            Strategy::Random(_) => {
+                if self.items.is_empty() {
+                    return None;
+                }
+                self.rng ^= self.rng << 13;
+                self.rng ^= self.rng >> 7;
+                self.rng ^= self.rng << 17;
+                let idx = (self.rng % self.items.len() as u64) as usize;
+                self.items.swap_remove_back(idx)
+            }
+        }
+    }
+
+    /// Bfs takes everything, the others up to max items in their order
+    pub fn pop_batch(&mut self, max: usize) -> Vec<T> {
+        match self.strategy {
+            Strategy::Bfs => self.items.drain(..).collect(),
+            _ => std::iter::from_fn(|| self.pop()).take(max.max(1)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sync_reachability_graph::options::Strategy;
+
+    use super::Worklist;
+
+    #[test]
+    fn test_worklist_order() {
+        let order = |strategy| {
+            let mut list = Worklist::new(strategy);
+            (0..5).for_each(|i| list.push(i));
+            std::iter::from_fn(|| list.pop()).collect::<Vec<_>>()
+        };
+        assert_eq!(order(Strategy::Bfs), vec![0, 1, 2, 3, 4]);
+        assert_eq!(order(Strategy::Dfs), vec![4, 3, 2, 1, 0]);
+
+        let random = order(Strategy::Random(7));
+        assert_eq!(random, order(Strategy::Random(7)));
+        let mut sorted = random.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+
+        let batches = |strategy| {
+            let mut list = Worklist::new(strategy);
+            (0..5).for_each(|i| list.push(i));
+            std::iter::from_fn(|| Some(list.pop_batch(2)).filter(|b| !b.is_empty()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(batches(Strategy::Bfs), vec![vec![0, 1, 2, 3, 4]]);
+        assert_eq!(
+            batches(Strategy::Dfs),
+            vec![vec![4, 3], vec![2, 1], vec![0]]
+        );
+        assert_eq!(batches(Strategy::Random(7)).concat(), random);
+    }
+}
@@ -0,0 +1,22 @@
+use std::fs;
+
+use crate::{
+    modular_net::ModularPetrinet,
+    parser::{parse_input::parse, transform_input::transform},
+};
+
+/// read the text of an example, relative to the examples directory
+pub(crate) fn read(path: &str) -> String {
+    let path = format!("{}/examples/{}", env!("CARGO_MANIFEST_DIR"), path);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e))
+}
+
+/// parse and transform an example net
+pub(crate) fn example(path: &str) -> ModularPetrinet {
+    net(&read(path))
+}
+
+/// parse and transform a net written in the test
+pub(crate) fn net(input: &str) -> ModularPetrinet {
+    transform(parse(input).unwrap().1)
+}